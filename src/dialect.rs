@@ -0,0 +1,340 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::Result;
+
+/// The byte order mark some programs put at the start of utf-8 files.
+pub const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// What ends each record.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Terminator {
+    Lf,
+    Crlf,
+    Other(u8),
+}
+
+/// How a csv is written: the characters separating and quoting fields.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    /// Escapes quotes inside quoted fields, or doubles them if `None`
+    pub escape: Option<u8>,
+    pub terminator: Terminator,
+    /// Trims whitespace around fields when reading
+    pub trim: bool,
+    /// Quotes every field written, not just those that need it
+    pub quote_all: bool,
+    /// Starts the file with a byte order mark
+    pub bom: bool,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            terminator: Terminator::Lf,
+            trim: false,
+            quote_all: false,
+            bom: false,
+        }
+    }
+}
+
+/// Delimiters guessed from the first lines of a file, most likely first.
+/// Not spaces, which single column files often have in every value.
+const CANDIDATES: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+/// How many lines are read to guess the dialect.
+const SNIFF_LINES: usize = 20;
+
+impl Dialect {
+    /// Guesses the dialect of the file at the path from its extension,
+    /// or from its first lines if the extension doesn't say.
+    pub fn detect(path: &str) -> Self {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let by_ext = match ext.as_deref() {
+            Some("tsv") | Some("tab") => Some(b'\t'),
+            Some("psv") => Some(b'|'),
+            _ => None,
+        };
+
+        let lines = match File::open(path) {
+            Ok(f) => read_lines(f),
+            Err(_) => Vec::new(),
+        };
+        Self::sniff(&lines, by_ext)
+    }
+
+    /// Guesses the dialect of csv data from its first lines, as read from stdin.
+    pub fn detect_bytes(bytes: &[u8]) -> Self {
+        Self::sniff(&read_lines(bytes), None)
+    }
+
+    /// Guesses the dialect from the first lines, unless the delimiter is known.
+    fn sniff(lines: &[String], delimiter: Option<u8>) -> Self {
+        let mut d = Dialect::default();
+        if lines.iter().any(|l| l.ends_with("\r\n")) {
+            d.terminator = Terminator::Crlf;
+        }
+        d.bom = lines.first().is_some_and(|l| l.as_bytes().starts_with(BOM));
+        d.delimiter = delimiter.or_else(|| sniff_delimiter(lines, d.quote)).unwrap_or(b',');
+        d.quote_all = !lines.is_empty() && lines.iter().all(|l| all_quoted(l, d.delimiter, d.quote));
+        d
+    }
+
+    /// Changes a setting by name, as from `:set delimiter=;` or `--delimiter ';'`.
+    /// Settings are `delimiter`, `quote`, `escape`, `terminator`, `trim`,
+    /// `quoteall` and `bom`.
+    pub fn set(&mut self, key: &str, value: &str) -> std::result::Result<(), String> {
+        match key {
+            "delimiter" | "delim" => self.delimiter = parse_char(value)?,
+            "quote" => self.quote = parse_char(value)?,
+            "escape" => {
+                self.escape = match value {
+                    "double" | "none" => None,
+                    _ => Some(parse_char(value)?),
+                }
+            }
+            "terminator" => {
+                self.terminator = match value.to_lowercase().as_str() {
+                    "lf" => Terminator::Lf,
+                    "crlf" => Terminator::Crlf,
+                    _ => Terminator::Other(parse_char(value)?),
+                }
+            }
+            "trim" => self.trim = parse_bool(key, value)?,
+            "quoteall" => self.quote_all = parse_bool(key, value)?,
+            "bom" => self.bom = parse_bool(key, value)?,
+            "notrim" if value.is_empty() => self.trim = false,
+            "noquoteall" if value.is_empty() => self.quote_all = false,
+            "nobom" if value.is_empty() => self.bom = false,
+            _ => return Err(format!("Unknown option: {}", key)),
+        }
+        Ok(())
+    }
+
+    /// The bytes written at the end of each record.
+    pub fn terminator_bytes(&self) -> Vec<u8> {
+        match self.terminator {
+            Terminator::Lf => b"\n".to_vec(),
+            Terminator::Crlf => b"\r\n".to_vec(),
+            Terminator::Other(t) => vec![t],
+        }
+    }
+
+    /// Whether the byte ends records, where reading either kind of
+    /// line ending treats both `\r` and `\n` as terminators.
+    pub fn is_terminator(&self, b: u8) -> bool {
+        match self.terminator {
+            Terminator::Other(t) => b == t,
+            Terminator::Lf | Terminator::Crlf => b == b'\r' || b == b'\n',
+        }
+    }
+
+    /// Removes terminators, and the blank lines they make, from both ends of a row read.
+    pub fn trim_terminators<'a>(&self, line: &'a [u8]) -> &'a [u8] {
+        let start = line.iter().position(|b| !self.is_terminator(*b)).unwrap_or(line.len());
+        let end = line.iter().rposition(|b| !self.is_terminator(*b)).map_or(start, |i| i + 1);
+        &line[start..end]
+    }
+
+    /// Writes a row as a record, without its terminator.
+    pub fn write_row(&self, row: &[String]) -> Result<Vec<u8>> {
+        let mut wtr = self.writer().from_writer(Vec::new());
+        wtr.write_record(row)?;
+        let mut bytes = wtr
+            .into_inner()
+            .map_err(|e| io::Error::new(e.error().kind(), e.to_string()))?;
+        bytes.truncate(bytes.len() - self.terminator_bytes().len());
+        Ok(bytes)
+    }
+
+    pub fn reader(&self) -> csv::ReaderBuilder {
+        let mut b = csv::ReaderBuilder::new();
+        b.delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.escape.is_none())
+            .terminator(match self.terminator {
+                // reading either accepts both
+                Terminator::Lf | Terminator::Crlf => csv::Terminator::CRLF,
+                Terminator::Other(t) => csv::Terminator::Any(t),
+            })
+            .trim(if self.trim { csv::Trim::All } else { csv::Trim::None });
+        b
+    }
+
+    pub fn writer(&self) -> csv::WriterBuilder {
+        let mut b = csv::WriterBuilder::new();
+        b.delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape.unwrap_or(b'\\'))
+            .double_quote(self.escape.is_none())
+            .terminator(match self.terminator {
+                Terminator::Lf => csv::Terminator::Any(b'\n'),
+                Terminator::Crlf => csv::Terminator::CRLF,
+                Terminator::Other(t) => csv::Terminator::Any(t),
+            })
+            .quote_style(if self.quote_all { csv::QuoteStyle::Always } else { csv::QuoteStyle::Necessary });
+        b
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terminator = match self.terminator {
+            Terminator::Lf => "lf".to_string(),
+            Terminator::Crlf => "crlf".to_string(),
+            Terminator::Other(t) => char_name(t),
+        };
+        let escape = self.escape.map_or("double".to_string(), char_name);
+        write!(
+            f,
+            "delimiter={} quote={} escape={} terminator={} {} {} {}",
+            char_name(self.delimiter),
+            char_name(self.quote),
+            escape,
+            terminator,
+            if self.trim { "trim" } else { "notrim" },
+            if self.quote_all { "quoteall" } else { "noquoteall" },
+            if self.bom { "bom" } else { "nobom" },
+        )
+    }
+}
+
+/// Parses a single character setting, or the name of one.
+fn parse_char(s: &str) -> std::result::Result<u8, String> {
+    match s.to_lowercase().as_str() {
+        "tab" | "\\t" => return Ok(b'\t'),
+        "space" => return Ok(b' '),
+        "comma" => return Ok(b','),
+        "semicolon" => return Ok(b';'),
+        "pipe" => return Ok(b'|'),
+        _ => {}
+    }
+    match s.as_bytes() {
+        [b] if b.is_ascii() => Ok(*b),
+        _ => Err(format!("Expected a single character: {}", s)),
+    }
+}
+
+/// Parses an on or off setting, which is on if given no value.
+fn parse_bool(key: &str, value: &str) -> std::result::Result<bool, String> {
+    match value {
+        "" | "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("Invalid value for {}: {}", key, value)),
+    }
+}
+
+fn char_name(b: u8) -> String {
+    match b {
+        b'\t' => "tab".to_string(),
+        b' ' => "space".to_string(),
+        _ => (b as char).to_string(),
+    }
+}
+
+fn read_lines<R: Read>(f: R) -> Vec<String> {
+    let mut rdr = BufReader::new(f);
+    let mut lines = Vec::new();
+    for _ in 0..SNIFF_LINES {
+        let mut line = String::new();
+        match rdr.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => lines.push(line),
+        }
+    }
+    lines
+}
+
+/// Picks the delimiter that appears the same number of times, outside of
+/// quotes, on every line, preferring the most appearances.
+fn sniff_delimiter(lines: &[String], quote: u8) -> Option<u8> {
+    let lines: Vec<&String> = lines.iter().filter(|l| !l.trim().is_empty()).collect();
+    CANDIDATES
+        .iter()
+        .filter_map(|d| {
+            let counts: Vec<usize> = lines.iter().map(|l| count_unquoted(l, *d, quote)).collect();
+            let first = *counts.first()?;
+            if first > 0 && counts.iter().all(|c| *c == first) {
+                Some((*d, first))
+            } else {
+                None
+            }
+        })
+        // the earliest candidate wins a tie
+        .rev()
+        .max_by_key(|(_, n)| *n)
+        .map(|(d, _)| d)
+}
+
+fn count_unquoted(line: &str, delim: u8, quote: u8) -> usize {
+    let mut quoted = false;
+    let mut n = 0;
+    for b in line.bytes() {
+        if b == quote {
+            quoted = !quoted;
+        } else if b == delim && !quoted {
+            n += 1;
+        }
+    }
+    n
+}
+
+/// Whether every field on the line is quoted, judging by every
+/// delimiter outside of quotes being between two quotes.
+fn all_quoted(line: &str, delim: u8, quote: u8) -> bool {
+    let line = line.trim_start_matches('\u{feff}').trim_end_matches(&['\r', '\n'][..]);
+    if line.is_empty() {
+        return true;
+    }
+    let (q, d) = (quote as char, delim as char);
+    line.len() >= 2
+        && line.starts_with(q)
+        && line.ends_with(q)
+        && line.matches(&format!("{}{}{}", q, d, q)[..]).count() == count_unquoted(line, delim, quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_from_bytes() {
+        let d = Dialect::detect_bytes(b"\xEF\xBB\xBFa;b\r\n1;\"x;y\"\r\n");
+        assert_eq!(d.delimiter, b';');
+        assert_eq!(d.terminator, Terminator::Crlf);
+        assert!(d.bom);
+        assert!(!d.quote_all);
+        assert!(Dialect::detect_bytes(b"\"a\",\"b\"\n\"1\",\"2\"\n").quote_all);
+    }
+
+    #[test]
+    fn spaces_are_not_a_delimiter() {
+        let d = Dialect::detect_bytes(b"full name\nJohn Smith\nJane Doe\n");
+        assert_eq!(d.delimiter, b',');
+    }
+
+    #[test]
+    fn writes_a_row_without_its_terminator() {
+        let d = Dialect { terminator: Terminator::Crlf, ..Dialect::default() };
+        let row = vec!["x".to_string(), "a,b".to_string(), "say \"hi\"".to_string()];
+        assert_eq!(d.write_row(&row).unwrap(), b"x,\"a,b\",\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn trims_terminators_from_both_ends() {
+        let d = Dialect::default();
+        assert_eq!(d.trim_terminators(b"\r\n\"a\nb\",c\r\n"), b"\"a\nb\",c");
+    }
+}
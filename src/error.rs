@@ -0,0 +1,38 @@
+use std::fmt;
+use std::io;
+
+/// Errors from reading or writing a table.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Csv(csv::Error),
+    /// A streamed file can't be written until every row is found
+    Loading,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            Io(e) => write!(f, "{}", e),
+            Csv(e) => write!(f, "{}", e),
+            Loading => write!(f, "still loading the file, try again once it's done"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        Error::Csv(e)
+    }
+}
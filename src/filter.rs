@@ -0,0 +1,102 @@
+use std::cmp::Ordering;
+
+use regex::Regex;
+
+use crate::sort::Column;
+use crate::{Cursor, Table};
+
+/// How a cell is compared with the value of a filter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+/// Hides rows that don't match, like `:filter price > 10` or `:filter name ~ ^j`.
+///
+/// The operators are `=`, `!=`, `<`, `<=`, `>`, `>=`, and `~` and `!~` for
+/// matching a regular expression.  Values compare as numbers when both are
+/// numbers.  The column is a header, a column number or `.` for the cursor column.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    col: Column,
+    op: Op,
+    value: String,
+    re: Option<Regex>,
+}
+
+impl Filter {
+    /// Parses the words after `:filter`.
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let (col, op, value) = match args {
+            [col, op, value @ ..] => (col, op, value.join(" ")),
+            _ => return Err("Usage: filter <column> <op> <value>".to_string()),
+        };
+        let col = match *col {
+            "." => Column::Cursor,
+            name => Column::Named(name.to_string()),
+        };
+        let op = match *op {
+            "=" | "==" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "~" => Op::Match,
+            "!~" => Op::NotMatch,
+            _ => return Err(format!("Unknown operator: {}", op)),
+        };
+        let re = match op {
+            Op::Match | Op::NotMatch => match Regex::new(&value) {
+                Ok(re) => Some(re),
+                Err(_) => return Err(format!("Invalid pattern: {}", value)),
+            },
+            _ => None,
+        };
+        Ok(Self { col, op, value, re })
+    }
+
+    /// Hides the rows in view that don't match.
+    /// Returns the number of rows left in view, not counting the header.
+    pub fn apply(&self, table: &mut Table, c: Cursor) -> Result<usize, String> {
+        let x = match &self.col {
+            Column::Cursor => c.x,
+            Column::Named(name) => table
+                .find_col(name)
+                .ok_or_else(|| format!("No column named {}", name))?,
+        };
+        table
+            .filter_rows(|row| self.matches(&row[x]))
+            .ok_or_else(|| "No rows match".to_string())
+    }
+
+    fn matches(&self, cell: &str) -> bool {
+        if let Some(re) = &self.re {
+            return re.is_match(cell) == (self.op == Op::Match);
+        }
+        let o = match (cell.trim().parse::<f64>(), self.value.trim().parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            (Err(_), Err(_)) => Some(cell.cmp(&self.value)),
+            // a number and something else can only be unequal
+            _ => None,
+        };
+        match (self.op, o) {
+            (Op::Ne, None) => true,
+            (_, None) => false,
+            (Op::Eq, Some(o)) => o == Ordering::Equal,
+            (Op::Ne, Some(o)) => o != Ordering::Equal,
+            (Op::Lt, Some(o)) => o == Ordering::Less,
+            (Op::Le, Some(o)) => o != Ordering::Greater,
+            (Op::Gt, Some(o)) => o == Ordering::Greater,
+            (Op::Ge, Some(o)) => o != Ordering::Less,
+            (Op::Match, _) | (Op::NotMatch, _) => unreachable!(),
+        }
+    }
+}
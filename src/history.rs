@@ -0,0 +1,141 @@
+use crate::Cursor;
+
+/// A single reversible change to the table data.
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Cell { x: usize, y: usize, old: String, new: String },
+    InsertRow { y: usize, row: Vec<String> },
+    DeleteRow { y: usize, row: Vec<String> },
+    InsertCol { x: usize, col: Vec<String> },
+    DeleteCol { x: usize, col: Vec<String> },
+    /// Reorders rows so row `i` becomes the old row `order[i]`
+    Permute { order: Vec<usize> },
+}
+
+impl Edit {
+    /// Returns the edit that undoes this one.
+    pub fn inverse(&self) -> Edit {
+        use Edit::*;
+        match self.clone() {
+            Cell { x, y, old, new } => Cell { x, y, old: new, new: old },
+            InsertRow { y, row } => DeleteRow { y, row },
+            DeleteRow { y, row } => InsertRow { y, row },
+            InsertCol { x, col } => DeleteCol { x, col },
+            DeleteCol { x, col } => InsertCol { x, col },
+            Permute { order } => {
+                let mut inverse = vec![0; order.len()];
+                for (i, o) in order.into_iter().enumerate() {
+                    inverse[o] = i;
+                }
+                Permute { order: inverse }
+            }
+        }
+    }
+
+    /// Performs the edit on the raw table data.
+    pub fn apply(&self, data: &mut Vec<Vec<String>>) {
+        use Edit::*;
+        match self {
+            Cell { x, y, new, .. } => data[*y][*x] = new.clone(),
+            InsertRow { y, row } => data.insert(*y, row.clone()),
+            DeleteRow { y, .. } => {
+                data.remove(*y);
+            }
+            InsertCol { x, col } => {
+                for (row, cell) in data.iter_mut().zip(col.iter()) {
+                    row.insert(*x, cell.clone());
+                }
+            }
+            DeleteCol { x, .. } => {
+                for row in data.iter_mut() {
+                    row.remove(*x);
+                }
+            }
+            Permute { order } => {
+                let mut old: Vec<Option<Vec<String>>> = data.drain(..).map(Some).collect();
+                data.extend(order.iter().map(|i| old[*i].take().unwrap()));
+            }
+        }
+    }
+}
+
+/// A group of edits that is undone or redone at once,
+/// along with where the cursor was when it started.
+#[derive(Debug)]
+pub struct Step {
+    pub edits: Vec<Edit>,
+    pub cursor: Cursor,
+}
+
+/// Undo and redo stacks of steps.
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    group: Option<Step>,
+    depth: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an edit made with the cursor at `c`.
+    /// Any undone steps can no longer be redone.
+    pub fn record(&mut self, edit: Edit, c: Cursor) {
+        self.redo.clear();
+        match &mut self.group {
+            Some(step) => step.edits.push(edit),
+            None => self.undo.push(Step { edits: vec![edit], cursor: c }),
+        }
+    }
+
+    /// Starts collecting edits into a single step, until `end_group`.
+    /// Groups may be nested, and only the outermost one makes a step.
+    pub fn begin_group(&mut self, c: Cursor) {
+        if self.group.is_none() {
+            self.group = Some(Step { edits: Vec::new(), cursor: c });
+        }
+        self.depth += 1;
+    }
+
+    /// Finishes the current group, discarding it if nothing was edited.
+    pub fn end_group(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth > 0 {
+            return
+        }
+        if let Some(step) = self.group.take() {
+            if !step.edits.is_empty() {
+                self.undo.push(step);
+            }
+        }
+    }
+
+    /// Closes any open groups.
+    fn close_groups(&mut self) {
+        self.depth = 1;
+        self.end_group();
+    }
+
+    /// Takes the most recent step off the undo stack.
+    pub fn pop_undo(&mut self) -> Option<Step> {
+        self.close_groups();
+        self.undo.pop()
+    }
+
+    /// Takes the most recently undone step off the redo stack.
+    pub fn pop_redo(&mut self) -> Option<Step> {
+        self.close_groups();
+        self.redo.pop()
+    }
+
+    pub fn push_undo(&mut self, step: Step) {
+        self.undo.push(step);
+    }
+
+    pub fn push_redo(&mut self, step: Step) {
+        self.redo.push(step);
+    }
+}
@@ -1,6 +1,14 @@
-use crossterm::event::{self, Event, KeyModifiers, KeyCode};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyEvent, KeyModifiers, KeyCode};
 use crate::Mode;
+use crate::Object;
 use crate::PromptType;
+use crate::Sort;
+use crate::Column;
+use crate::filter;
+use crate::Visual;
+use crate::substitute;
 
 #[derive(Clone, Copy, Debug)]
 pub enum Dir {
@@ -10,8 +18,10 @@ pub enum Dir {
 
 /// Actions are unique and have the same
 /// behavior no matter the current mode.
+#[derive(Clone)]
 pub enum Action {
-    MoveCursor(Dir),
+    /// Moves the cursor this many rows or columns, or to an edge of the table
+    MoveCursor(Dir, usize),
 
     EnterPrompt(PromptType),
     Prompt(PromptType, PromptAction),
@@ -27,16 +37,105 @@ pub enum Action {
 
     AddRowAbove,
     AddRowBelow,
-    DeleteRow,
+    /// Deletes this many rows into the unnamed register
+    DeleteRow(usize),
 
     AddColLeft,
     AddColRight,
     DeleteCol,
 
+    /// Yanks this many cells, rows or columns into the register
+    Yank(Option<char>, Object, usize),
+    /// Puts this many copies of the register after the cursor, or before if true
+    Put(Option<char>, bool, usize),
+
+    ClearSelection,
+    /// Deletes the rows of the selection, or the columns if selecting columns
+    DeleteSelection,
+
+    /// Undoes this many changes, or as many as there are
+    Undo(usize),
+    Redo(usize),
+
+    Freeze(usize),
+    /// Limits how wide a column is drawn, or gives it the usual limit if `None`
+    ColWidth(Column, Option<Option<usize>>),
+    /// Limits how wide every column is drawn, or not at all if `None`
+    MaxWidth(Option<usize>),
+    /// Shows or hides the full value of the cell under the cursor
+    ToggleDetail,
+
+    SearchNext,
+    SearchPrev,
+    Substitute(substitute::Substitute),
+    Sort(Sort),
+    Filter(filter::Filter),
+    Unfilter,
+
+    /// Changes a dialect setting, with an empty value for settings like `trim`
+    Set(String, String),
+    /// Shows every dialect setting
+    ShowSettings,
+    /// Reads the file again in the current dialect, dropping all changes
+    Reload,
+
     Save,
+    /// Writes the table to another file, leaving the file being edited as it was
+    Export(String),
+    /// Saves the table to another file, which is then the file being edited
+    SaveAs(String),
     Quit,
+    ForceQuit,
+
+    Resize,
+    GoToRow(usize),
+
+    Error(String),
 }
 
+impl Action {
+    /// Whether a count typed before the key repeats the action.
+    fn repeats(&self) -> bool {
+        use Action::*;
+        matches!(self,
+            ClearCell
+            | AddRowAbove | AddRowBelow
+            | AddColLeft | AddColRight | DeleteCol
+            | SearchNext | SearchPrev
+        )
+    }
+
+    /// Whether the action changes the table or the file, or starts typing
+    /// a change, which a read-only table refuses.
+    pub fn modifies(&self) -> bool {
+        use Action::*;
+        matches!(self,
+            Append(_) | Pop | CarriageReturn | ClearCell
+            | AddRowAbove | AddRowBelow | DeleteRow(_)
+            | AddColLeft | AddColRight | DeleteCol
+            | Put(..) | ClearSelection | DeleteSelection
+            | Undo(_) | Redo(_) | Substitute(_) | Sort(_) | Save | SaveAs(_)
+            | EnterMode(Mode::Insert)
+            | EnterPrompt(PromptType::EditReplace | PromptType::EditAppend)
+            | EnterPrompt(PromptType::Fill | PromptType::AppendEach)
+        )
+    }
+
+    /// Whether the action adds, removes, reorders or hides rows or columns,
+    /// or reads a whole column, which a streamed table can't do.
+    pub fn reshapes(&self) -> bool {
+        use Action::*;
+        matches!(self,
+            AddRowAbove | AddRowBelow | DeleteRow(_)
+            | AddColLeft | AddColRight | DeleteCol
+            | DeleteSelection | Sort(_) | Filter(_)
+            | Yank(_, Object::Col, _)
+            | EnterMode(Mode::Visual(Visual::Cols))
+        )
+    }
+}
+
+#[derive(Clone)]
 pub enum PromptAction {
     Push(char),
     Backspace,
@@ -44,77 +143,203 @@ pub enum PromptAction {
     Exit,
 }
 
-pub fn get_actions(mode: Mode) -> Vec<Action> {
-    match mode {
-        Mode::Table => table_mode_actions(),
-        Mode::Prompt(p) => prompt_mode_actions(p),
-        Mode::Insert => insert_mode_actions(),
-        _ => Vec::new(),
+/// Reads keys, remembering a count, register and the first
+/// key of a two key command typed in table mode
+/// until the key they apply to.
+#[derive(Default)]
+pub struct Input {
+    count: Option<usize>,
+    register: Option<char>,
+    pending: Option<char>,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits up to the timeout for an event, returning whether one came.
+    pub fn ready(&self, timeout: Duration) -> bool {
+        event::poll(timeout).unwrap()
+    }
+
+    /// Waits for the next event and returns what it should do in the mode.
+    /// Resizing the terminal does the same thing in every mode.
+    /// An action is repeated by a count at most `limit` times.
+    pub fn get_actions(&mut self, mode: Mode, limit: usize) -> Vec<Action> {
+        match event::read().unwrap() {
+            Event::Resize(_, _) => vec![Action::Resize],
+            Event::Key(keyevent) => match mode {
+                Mode::Table => self.counted_actions(keyevent, limit),
+                Mode::Prompt(p) => prompt_mode_actions(p, keyevent),
+                Mode::Insert => insert_mode_actions(keyevent),
+                Mode::Visual(kind) => visual_mode_actions(kind, keyevent),
+                _ => Vec::new(),
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Handles a count and register before a key in table mode,
+    /// like `10j`, `5G` or `"a3yy`.
+    fn counted_actions(&mut self, keyevent: KeyEvent, limit: usize) -> Vec<Action> {
+        use Action::*;
+        if let KeyCode::Char(ch) = keyevent.code {
+            if self.pending == Some('"') {
+                self.register = Some(ch);
+                self.pending = None;
+                return Vec::new();
+            }
+            // 0 on its own is a motion
+            let digit = ch.is_ascii_digit() && (ch != '0' || self.count.is_some());
+            if self.pending.is_none() && digit {
+                let d = ch.to_digit(10).unwrap() as usize;
+                let count = self.count.unwrap_or(0).saturating_mul(10).saturating_add(d);
+                self.count = Some(count);
+                return Vec::new();
+            }
+            if self.pending.is_none() && (ch == '"' || ch == 'y') {
+                self.pending = Some(ch);
+                return Vec::new();
+            }
+        }
+
+        // anything else finishes the command
+        let count = self.count.take();
+        let register = self.register.take();
+        let n = count.unwrap_or(1);
+        if let Some(first) = self.pending.take() {
+            return match (first, keyevent.code) {
+                ('y', KeyCode::Char('y')) => vec![Yank(register, Object::Row, n)],
+                ('y', KeyCode::Char('c')) => vec![Yank(register, Object::Col, n)],
+                ('y', KeyCode::Char('l')) => vec![Yank(register, Object::Cell, n)],
+                _ => Vec::new(),
+            };
+        }
+        if keyevent.code == KeyCode::Esc && (count.is_some() || register.is_some()) {
+            return Vec::new();
+        }
+
+        let mut actions = Vec::new();
+        for action in table_mode_actions(keyevent) {
+            match action {
+                MoveCursor(Dir::Bottom, _) if count.is_some() => actions.push(GoToRow(n)),
+                MoveCursor(dir, _) => actions.push(MoveCursor(dir, n)),
+                Undo(_) => actions.push(Undo(n)),
+                Redo(_) => actions.push(Redo(n)),
+                DeleteRow(_) => actions.push(DeleteRow(n)),
+                Put(_, before, _) => actions.push(Put(register, before, n.min(limit))),
+                a if a.repeats() => actions.extend(vec![a; n.min(limit)]),
+                a => actions.push(a),
+            }
+        }
+        actions
     }
 }
 
-pub fn prompt_mode_actions(p: PromptType) -> Vec<Action> {
+pub fn prompt_mode_actions(p: PromptType, keyevent: KeyEvent) -> Vec<Action> {
     use Action::*;
     use PromptAction::*;
-    match event::read().unwrap() {
-        Event::Key(keyevent) => match keyevent.code {
-            KeyCode::Esc => vec![Prompt(p, Exit)],
-            KeyCode::Enter => vec![Prompt(p, Submit)],
-            KeyCode::Backspace => vec![Prompt(p, Backspace)],
-            KeyCode::Char(c) => vec![Prompt(p, Push(c))],
-            _ => Vec::new(),
-        }
+    match keyevent.code {
+        KeyCode::Esc => vec![Prompt(p, Exit)],
+        KeyCode::Enter => vec![Prompt(p, Submit)],
+        KeyCode::Backspace => vec![Prompt(p, Backspace)],
+        KeyCode::Char(c) => vec![Prompt(p, Push(c))],
         _ => Vec::new(),
     }
 }
 
-pub fn insert_mode_actions() -> Vec<Action> {
+pub fn insert_mode_actions(keyevent: KeyEvent) -> Vec<Action> {
     use Action::*;
-    match event::read().unwrap() {
-        Event::Key(keyevent) => match (keyevent.code, keyevent.modifiers.contains(KeyModifiers::SHIFT)) {
-            (KeyCode::Up, _) => vec![MoveCursor(Dir::Up)],
-            (KeyCode::Down, _) => vec![MoveCursor(Dir::Down)],
-            (KeyCode::Left, _) => vec![MoveCursor(Dir::Left)],
-            (KeyCode::Right, _) => vec![MoveCursor(Dir::Right)],
-
-            (KeyCode::Esc, _) => vec![EnterMode(Mode::Table)],
-            (KeyCode::Backspace, _) => vec![Pop],
-            (KeyCode::Tab, _) => vec![MoveCursor(Dir::Right)],
-            (KeyCode::BackTab, _) => vec![MoveCursor(Dir::Left)],
-            (KeyCode::Enter, _) => vec![CarriageReturn],
-            (KeyCode::Char(c), _) => vec![Append(c)],
-            _ => Vec::new(),
-        }
+    match (keyevent.code, keyevent.modifiers.contains(KeyModifiers::SHIFT)) {
+        (KeyCode::Up, _) => vec![MoveCursor(Dir::Up, 1)],
+        (KeyCode::Down, _) => vec![MoveCursor(Dir::Down, 1)],
+        (KeyCode::Left, _) => vec![MoveCursor(Dir::Left, 1)],
+        (KeyCode::Right, _) => vec![MoveCursor(Dir::Right, 1)],
+
+        (KeyCode::Esc, _) => vec![EnterMode(Mode::Table)],
+        (KeyCode::Backspace, _) => vec![Pop],
+        (KeyCode::Tab, _) => vec![MoveCursor(Dir::Right, 1)],
+        (KeyCode::BackTab, _) => vec![MoveCursor(Dir::Left, 1)],
+        (KeyCode::Enter, _) => vec![CarriageReturn],
+        (KeyCode::Char(c), _) => vec![Append(c)],
         _ => Vec::new(),
     }
 }
 
-pub fn table_mode_actions() -> Vec<Action> {
+pub fn visual_mode_actions(kind: Visual, keyevent: KeyEvent) -> Vec<Action> {
     use Action::*;
-    match event::read().unwrap() {
-        Event::Key(keyevent) => match keyevent.code {
-            KeyCode::Char('k') | KeyCode::Up => vec![MoveCursor(Dir::Up)],
-            KeyCode::Char('j') | KeyCode::Down => vec![MoveCursor(Dir::Down)],
-            KeyCode::Char('h') | KeyCode::Left => vec![MoveCursor(Dir::Left)],
-            KeyCode::Char('l') | KeyCode::Right => vec![MoveCursor(Dir::Right)],
-            KeyCode::Char('g') => vec![MoveCursor(Dir::Top)],
-            KeyCode::Char('G') => vec![MoveCursor(Dir::Bottom)],
-            KeyCode::Char('0') => vec![MoveCursor(Dir::Start)],
-            KeyCode::Char('$') => vec![MoveCursor(Dir::End)],
-
-            KeyCode::Esc => vec![Quit],
-            KeyCode::Char('S') => vec![ClearCell],
-            KeyCode::Char('o') => vec![AddRowBelow],
-            KeyCode::Char('O') => vec![AddRowAbove],
-            KeyCode::Char('D') => vec![DeleteRow],
-
-            KeyCode::Char('I') => vec![EnterMode(Mode::Insert)],
-
-            KeyCode::Char('c') => vec![EnterPrompt(PromptType::EditReplace)],
-            KeyCode::Char('a') => vec![EnterPrompt(PromptType::EditAppend)],
-            KeyCode::Char(':') => vec![EnterPrompt(PromptType::Command)],
-            _ => Vec::new(),
-        }
+    let ctrl = keyevent.modifiers.contains(KeyModifiers::CONTROL);
+    match keyevent.code {
+        KeyCode::Char('k') | KeyCode::Up => vec![MoveCursor(Dir::Up, 1)],
+        KeyCode::Char('j') | KeyCode::Down => vec![MoveCursor(Dir::Down, 1)],
+        KeyCode::Char('h') | KeyCode::Left => vec![MoveCursor(Dir::Left, 1)],
+        KeyCode::Char('l') | KeyCode::Right => vec![MoveCursor(Dir::Right, 1)],
+        KeyCode::Char('g') => vec![MoveCursor(Dir::Top, 1)],
+        KeyCode::Char('G') => vec![MoveCursor(Dir::Bottom, 1)],
+        KeyCode::Char('0') => vec![MoveCursor(Dir::Start, 1)],
+        KeyCode::Char('$') => vec![MoveCursor(Dir::End, 1)],
+
+        // the key for the current kind of selection leaves visual mode
+        KeyCode::Char('v') if ctrl => vec![EnterMode(switch_visual(kind, Visual::Cols))],
+        KeyCode::Char('v') => vec![EnterMode(switch_visual(kind, Visual::Block))],
+        KeyCode::Char('V') => vec![EnterMode(switch_visual(kind, Visual::Rows))],
+        KeyCode::Esc => vec![EnterMode(Mode::Table)],
+
+        KeyCode::Char('y') => vec![Yank(None, Object::Selection, 1), EnterMode(Mode::Table)],
+        KeyCode::Char('x') | KeyCode::Char('S') => vec![ClearSelection],
+        KeyCode::Char('d') | KeyCode::Char('D') => vec![DeleteSelection],
+        KeyCode::Char('c') => vec![EnterPrompt(PromptType::Fill)],
+        KeyCode::Char('a') => vec![EnterPrompt(PromptType::AppendEach)],
+        _ => Vec::new(),
+    }
+}
+
+fn switch_visual(from: Visual, to: Visual) -> Mode {
+    if from == to {
+        Mode::Table
+    } else {
+        Mode::Visual(to)
+    }
+}
+
+pub fn table_mode_actions(keyevent: KeyEvent) -> Vec<Action> {
+    use Action::*;
+    let ctrl = keyevent.modifiers.contains(KeyModifiers::CONTROL);
+    match keyevent.code {
+        KeyCode::Char('r') if ctrl => vec![Redo(1)],
+        KeyCode::Char('v') if ctrl => vec![EnterMode(Mode::Visual(Visual::Cols))],
+
+        KeyCode::Char('k') | KeyCode::Up => vec![MoveCursor(Dir::Up, 1)],
+        KeyCode::Char('j') | KeyCode::Down => vec![MoveCursor(Dir::Down, 1)],
+        KeyCode::Char('h') | KeyCode::Left => vec![MoveCursor(Dir::Left, 1)],
+        KeyCode::Char('l') | KeyCode::Right => vec![MoveCursor(Dir::Right, 1)],
+        KeyCode::Char('g') => vec![MoveCursor(Dir::Top, 1)],
+        KeyCode::Char('G') => vec![MoveCursor(Dir::Bottom, 1)],
+        KeyCode::Char('0') => vec![MoveCursor(Dir::Start, 1)],
+        KeyCode::Char('$') => vec![MoveCursor(Dir::End, 1)],
+
+        KeyCode::Esc => vec![Quit],
+        KeyCode::Char('S') => vec![ClearCell],
+        KeyCode::Char('o') => vec![AddRowBelow],
+        KeyCode::Char('O') => vec![AddRowAbove],
+        KeyCode::Char('D') => vec![DeleteRow(1)],
+        KeyCode::Char('p') => vec![Put(None, false, 1)],
+        KeyCode::Char('P') => vec![Put(None, true, 1)],
+        KeyCode::Char('u') => vec![Undo(1)],
+        KeyCode::Char('K') => vec![ToggleDetail],
+
+        KeyCode::Char('I') => vec![EnterMode(Mode::Insert)],
+        KeyCode::Char('v') => vec![EnterMode(Mode::Visual(Visual::Block))],
+        KeyCode::Char('V') => vec![EnterMode(Mode::Visual(Visual::Rows))],
+
+        KeyCode::Char('c') => vec![EnterPrompt(PromptType::EditReplace)],
+        KeyCode::Char('a') => vec![EnterPrompt(PromptType::EditAppend)],
+        KeyCode::Char(':') => vec![EnterPrompt(PromptType::Command)],
+        KeyCode::Char('/') => vec![EnterPrompt(PromptType::SearchForward)],
+        KeyCode::Char('?') => vec![EnterPrompt(PromptType::SearchBackward)],
+        KeyCode::Char('n') => vec![SearchNext],
+        KeyCode::Char('N') => vec![SearchPrev],
         _ => Vec::new(),
     }
 }
@@ -122,11 +347,94 @@ pub fn table_mode_actions() -> Vec<Action> {
 /// Returns a Command from a string typed at the command prompt
 pub fn from_prompt(s: String) -> Vec<Action> {
     use Action::*;
-    match s.to_lowercase().as_str() {
-        "w" | "write" => vec![Save],
-        "q" | "quit" => vec![Quit],
-        "addcol" => vec![AddColRight],
-        "delcol" => vec![DeleteCol],
-        _ => Vec::new(),
+    // patterns are case sensitive, so check before lowercasing
+    match substitute::Substitute::parse(&s) {
+        Some(Ok(sub)) => return vec![Substitute(sub)],
+        Some(Err(e)) => return vec![Error(e)],
+        None => {}
+    }
+
+    // only the command itself ignores case
+    let words: Vec<&str> = s.split_whitespace().collect();
+    let (cmd, args) = match words.split_first() {
+        Some((cmd, args)) => (cmd.to_lowercase(), args),
+        None => return Vec::new(),
+    };
+    match (cmd.as_str(), args) {
+        ("w", []) | ("write", []) => vec![Save],
+        ("w", path) | ("write", path) => vec![Export(path.join(" "))],
+        ("sav", path) | ("saveas", path) if !path.is_empty() => vec![SaveAs(path.join(" "))],
+        ("q", []) | ("quit", []) => vec![Quit],
+        ("q!", []) | ("quit!", []) => vec![ForceQuit],
+        ("wq", []) | ("x", []) => vec![Save, Quit],
+        ("addcol", []) => vec![AddColRight],
+        ("delcol", []) => vec![DeleteCol],
+        ("undo", []) => vec![Undo(1)],
+        ("redo", []) => vec![Redo(1)],
+        ("freeze", [n]) => match n.parse() {
+            Ok(n) => vec![Freeze(n)],
+            Err(_) => Vec::new(),
+        },
+        ("unfreeze", []) => vec![Freeze(0)],
+        ("colwidth", [n]) => colwidth_command(Column::Cursor, n),
+        ("colwidth", [col, n]) => match *col {
+            "." => colwidth_command(Column::Cursor, n),
+            col => colwidth_command(Column::Named(col.to_string()), n),
+        },
+        ("maxwidth", [n]) => match parse_width(n) {
+            Ok(max) => vec![MaxWidth(max)],
+            Err(e) => vec![Error(e)],
+        },
+        ("sort", args) => sort_command(args, false),
+        ("sort!", args) => sort_command(args, true),
+        ("filter", args) => match filter::Filter::parse(args) {
+            Ok(filter) => vec![Filter(filter)],
+            Err(e) => vec![Error(e)],
+        },
+        ("unfilter", []) => vec![Unfilter],
+        ("set", []) => vec![ShowSettings],
+        ("set", args) => args
+            .iter()
+            .map(|a| match a.split_once('=') {
+                Some((key, value)) => Set(key.to_lowercase(), value.to_string()),
+                None => Set(a.to_lowercase(), String::new()),
+            })
+            .collect(),
+        ("e!", []) | ("edit!", []) => vec![Reload],
+        // a row number on its own jumps to that row
+        _ => match (cmd.parse(), args) {
+            (Ok(n), []) => vec![GoToRow(n)],
+            _ => vec![Error(format!("Not a command: {}", s))],
+        },
+    }
+}
+
+/// Parses a column width, where `0` or `none` means no limit.
+fn parse_width(s: &str) -> Result<Option<usize>, String> {
+    match s {
+        "none" => Ok(None),
+        _ => match s.parse() {
+            Ok(0) => Ok(None),
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(format!("Not a width: {}", s)),
+        },
+    }
+}
+
+/// A width of `auto` gives the column the limit every column has.
+fn colwidth_command(col: Column, n: &str) -> Vec<Action> {
+    if n == "auto" {
+        return vec![Action::ColWidth(col, None)];
+    }
+    match parse_width(n) {
+        Ok(max) => vec![Action::ColWidth(col, Some(max))],
+        Err(e) => vec![Action::Error(e)],
+    }
+}
+
+fn sort_command(args: &[&str], desc: bool) -> Vec<Action> {
+    match Sort::parse(args, desc) {
+        Ok(sort) => vec![Action::Sort(sort)],
+        Err(e) => vec![Action::Error(e)],
     }
 }
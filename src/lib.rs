@@ -1,29 +1,56 @@
 use crossterm::terminal;
 
+mod dialect;
+pub use dialect::{Dialect, Terminator};
+mod error;
+mod filter;
+pub use filter::Filter;
+pub use error::{Error, Result};
 pub mod input;
+mod history;
+mod register;
+pub use register::{Object, Register, Registers};
 mod render;
-pub use render::Renderer;
+pub use render::{Renderer, DETAIL_ROWS};
+mod search;
+pub use search::Search;
+mod selection;
+pub use selection::{Selection, Visual};
+mod sort;
+mod stream;
+pub use sort::{Column, Sort};
+mod substitute;
+pub use substitute::Substitute;
 mod table;
+mod text;
+mod types;
+pub use types::Type;
 pub use table::Table;
 pub use input::{Dir, PromptAction, Action};
 
 use std::fmt;
+use std::fs;
+use std::path::Path;
 
 #[derive(Clone, Copy, Debug)]
 pub enum Mode {
     Prompt(PromptType),
     Table,
     Insert,
+    Visual(Visual),
     Exit,  // could remove and use option<mode> otherwise
 }
 
 impl fmt::Display for Mode {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use Mode::*;
         let s = match self {
             Insert => "Insert Mode",
             Table => "Movement Mode",
             Prompt(_) => "Prompt",
+            Visual(selection::Visual::Block) => "Visual Mode",
+            Visual(selection::Visual::Rows) => "Visual Row Mode",
+            Visual(selection::Visual::Cols) => "Visual Column Mode",
             _ => "",
         };
         write!(f, "{}", s)
@@ -35,6 +62,10 @@ pub enum PromptType {
     EditReplace,
     EditAppend,
     Command,
+    SearchForward,
+    SearchBackward,
+    Fill,
+    AppendEach,
 }
 
 impl PromptType {
@@ -44,12 +75,16 @@ impl PromptType {
             EditReplace => "edit: ",
             EditAppend => "edit: ",
             Command => ":",
+            SearchForward => "/",
+            SearchBackward => "?",
+            Fill => "fill: ",
+            AppendEach => "append: ",
         }.to_string()
     }
 }
 
 /// needs a table for context (also usize)
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Cursor {
     pub x: usize,
     pub y: usize,
@@ -60,20 +95,29 @@ impl Cursor {
         Self { x: 0, y: 0 }
     }
 
-    pub fn move_dir(&mut self, dir: Dir, table: &Table) {
+    /// Moves the cursor back inside the table if it fell outside.
+    pub fn clamp(&mut self, table: &Table) {
+        let (w, h) = table.dims();
+        self.x = self.x.min(w - 1);
+        self.y = self.y.min(h - 1);
+    }
+
+    /// Moves `n` rows or columns, stopping at the edge of the table.
+    /// Moving to an edge ignores `n`.
+    pub fn move_dir(&mut self, dir: Dir, n: usize, table: &Table) {
         use Dir::*;
         match dir {
-            Up => if self.y > 0 {
-                self.y -= 1;
+            Up => {
+                self.y = self.y.saturating_sub(n);
             }
-            Down => if self.y < table.dims().1 - 1 {
-                self.y += 1;
+            Down => {
+                self.y = self.y.saturating_add(n).min(table.dims().1 - 1);
             }
-            Left => if self.x > 0 {
-                self.x -= 1;
+            Left => {
+                self.x = self.x.saturating_sub(n);
             }
-            Right => if self.x < table.dims().0 - 1 {
-                self.x += 1;
+            Right => {
+                self.x = self.x.saturating_add(n).min(table.dims().0 - 1);
             }
             Top => {
                 self.y = 0;
@@ -91,18 +135,50 @@ impl Cursor {
     }
 }
 
-pub fn align_anchor(anchor: &mut Cursor, c: Cursor) {
+/// Scrolls the anchor so the cursor is on screen.
+/// The anchor is the first row shown below the header row,
+/// and the first column shown after the `frozen` columns.
+/// The bottom `pane` rows of the table are covered.
+pub fn align_anchor(anchor: &mut Cursor, c: Cursor, table: &Table, frozen: usize, pane: usize) {
     let (w, hs) = terminal::size().unwrap();
-    let h = hs as usize;
-    
-    // is cursor above anchor?
-    if anchor.y > c.y {
-        anchor.y = c.y;
+    // rows left after the header, status bar, prompt and pane
+    let rows = (hs as usize).saturating_sub(3 + pane).max(1);
+
+    // the header row is always on screen
+    let start = table.body_start();
+    if c.y >= start {
+        // is cursor above anchor?
+        if anchor.y > c.y {
+            anchor.y = c.y;
+        }
+
+        // is cursor below screen?
+        if anchor.y + rows <= c.y {
+            anchor.y = c.y + 1 - rows; // order matters with usize
+        }
     }
+    anchor.y = anchor.y.max(start);
+
+    // frozen columns are always on screen
+    if c.x >= frozen {
+        anchor.x = render::scroll_start(anchor.x, frozen);
+
+        // is cursor left of anchor?
+        if anchor.x > c.x {
+            anchor.x = c.x;
+        }
+
+        // is cursor right of screen?
+        while anchor.x + render::visible_cols(table, frozen, anchor.x, w) <= c.x {
+            anchor.x += 1;
+        }
+    }
+}
 
-    // is cursor below screen?
-    // -2 is for padding
-    if anchor.y + h - 3 < c.y {
-        anchor.y = c.y + 3 - h; // order matters with usize
+/// Whether two paths name the same file, as far as can be told.
+pub fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => Path::new(a) == Path::new(b),
     }
 }
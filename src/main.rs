@@ -1,6 +1,14 @@
 use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
 
-use cic::{align_anchor, Action, PromptAction, Dir, PromptType, Mode, Cursor, Table, Renderer, input};
+use cic::{self, align_anchor, Action, PromptAction, Dir, PromptType, Mode, Cursor, Table, Renderer, Search, input};
+use cic::{same_file, Column, Type, DETAIL_ROWS};
+use cic::{Dialect, Object, Register, Registers, Selection, Visual};
+use crossterm::tty::IsTty;
 
 struct State {
     table: Table,
@@ -10,19 +18,75 @@ struct State {
     m: Mode,
     buf: String,
     path: String,
+    msg: String,
+    frozen: usize,
+    search: Option<Search>,
+    registers: Registers,
+    selection: Option<Selection>,
+    /// Whether the full value of the cell is shown
+    detail: bool,
+    /// Whether the status last showed the file still loading
+    loading: bool,
+    /// Whether changes are refused, allowing only writing a copy elsewhere
+    read_only: bool,
+    /// Whether saving writes the table to stdout on exit, rather than to the file
+    to_stdout: bool,
+    /// What was last saved for stdout
+    output: Option<Vec<u8>>,
 }
 
+/// Files at least this large are streamed rather than read at once.
+const STREAM_SIZE: u64 = 256 * 1024 * 1024;
+
+/// How often the table is redrawn while a streamed file loads.
+const LOADING_REFRESH: Duration = Duration::from_millis(200);
+
+const NOT_STREAMED: &str = "Not available while streaming a large file";
+
+const READ_ONLY: &str = "Read-only: use :w <path> to write a copy";
+
+const TOO_MANY_COPIES: &str = "Too many copies to put";
+
 impl State {
-    fn from_path(path: &String) -> Self {
-        Self {
-            table: Table::from_path(&path),
-            r: Renderer::new(),
-            c: Cursor::new(),
-            anchor: Cursor::new(),
-            m: Mode::Table,
-            buf: String::new(),
-            path: path.to_string(),
+    /// Loads the table before taking over the terminal,
+    /// so errors can be printed normally.
+    /// A path that doesn't exist yet is created when saved.
+    fn from_path(path: &str, dialect: Dialect, args: &Args) -> cic::Result<Self> {
+        let new = !Path::new(path).exists();
+        let large = fs::metadata(path).is_ok_and(|m| m.len() >= STREAM_SIZE);
+        let table = match &args.columns {
+            Some(names) if new => Table::with_columns(names.clone(), dialect),
+            _ if new => Table::from_path(path, dialect)?,
+            _ if args.stream || large => Table::stream_path(path, dialect)?,
+            _ => Table::from_path(path, dialect)?,
+        };
+        let mut s = Self::with_table(table, args);
+        s.path = path.to_string();
+        if new {
+            s.msg = format!("\"{}\" [New]", path);
         }
+        Ok(s)
+    }
+
+    /// Loads the table from a csv read from stdin, with no file to save
+    /// it to, so saving writes to stdout.
+    fn from_bytes(bytes: &[u8], dialect: Dialect, args: &Args) -> cic::Result<Self> {
+        let mut s = Self::with_table(Table::from_bytes(bytes, dialect)?, args);
+        s.to_stdout = true;
+        Ok(s)
+    }
+
+    fn with_table(mut table: Table, args: &Args) -> Self {
+        table.set_header(args.header);
+        if let Some(max) = args.max_width {
+            table.set_max_width(max);
+        }
+        let mut s = Self::new();
+        s.table = table;
+        s.read_only = args.read_only;
+        // a pipe can't show the table, so it gets what is saved
+        s.to_stdout = args.stdout || !io::stdout().is_tty();
+        s
     }
 
     fn new() -> Self {
@@ -33,7 +97,17 @@ impl State {
             anchor: Cursor::new(),
             m: Mode::Table,
             buf: String::new(),
-            path: String::new()
+            path: String::new(),
+            msg: String::new(),
+            frozen: 0,
+            search: None,
+            registers: Registers::new(),
+            selection: None,
+            detail: false,
+            loading: false,
+            read_only: false,
+            to_stdout: false,
+            output: None,
         }
     }
 
@@ -41,24 +115,133 @@ impl State {
         std::mem::take(&mut self.buf)
     }
 
-    fn set_buf(&mut self, s: &String) {
-        self.buf = s.clone();
+    fn set_buf(&mut self, s: &str) {
+        self.buf = s.to_string();
     }
 
     fn draw_table(&mut self) {
-        align_anchor(&mut self.anchor, self.c);
-        self.r.draw_table(&self.table, &self.c, &self.anchor);
+        let pane = if self.detail { DETAIL_ROWS } else { 0 };
+        align_anchor(&mut self.anchor, self.c, &self.table, self.frozen, pane);
+        self.r.set_pane(pane);
+        let selected = self.selected();
+        self.r.draw_table(&self.table, &self.c, &self.anchor, self.frozen, self.search.as_ref(), selected);
+        if self.detail {
+            let name = &self.table.col_names()[self.c.x];
+            let title = format!("{} (row {}, column {})", name, self.c.y, self.c.x);
+            self.r.draw_detail(&title, &self.table.get(self.c));
+        }
+    }
+
+    /// Changes the value under the cursor if it fits the type of the
+    /// column, other than in the header.  A value new to an enum column
+    /// is kept, but pointed out in case it's a typo.
+    fn replace_value(&mut self, value: String) {
+        let t = self.table.col_type(self.c.x);
+        if self.c.y < self.table.body_start() || t.parses(&value) {
+            self.table.update(self.c, value);
+            return
+        }
+        let name = &self.table.col_names()[self.c.x];
+        if let Type::Enum(_) = t {
+            self.msg = format!("New value for {}: {}", name, value);
+            self.table.update(self.c, value);
+        } else {
+            self.msg = format!("Expected {} in column {}, not \"{}\"", t, name, value);
+        }
+    }
+
+    /// Corners of the cells selected in visual mode, if any.
+    fn selected(&self) -> Option<(Cursor, Cursor)> {
+        self.selection.map(|sel| sel.bounds(self.c, &self.table))
+    }
+
+    fn draw_status(&mut self) {
+        let mut flags = Vec::new();
+        if self.read_only {
+            flags.push("[RO]".to_string());
+        }
+        if self.table.is_modified() {
+            flags.push("[+]".to_string());
+        }
+        if let Some((shown, total)) = self.table.filtered() {
+            flags.push(format!("[filtered {} of {}]", shown, total));
+        }
+        let loading = self.table.loading();
+        if let Some(percent) = loading {
+            flags.push(format!("[loading {}%]", percent));
+        }
+        self.loading = loading.is_some();
+        self.r.draw_status(self.m, &flags, &self.msg);
     }
 
     fn draw_prompt(&mut self, p: PromptType) {
         self.r.draw_prompt(p, &self.buf);
     }
 
+    /// Starts a search for the pattern, or repeats the last
+    /// search in a new direction if the pattern is empty.
+    fn start_search(&mut self, pattern: &str, backward: bool) {
+        if pattern.is_empty() {
+            if let Some(search) = &mut self.search {
+                search.backward = backward;
+            }
+        } else {
+            match Search::new(pattern, backward) {
+                Ok(search) => self.search = Some(search),
+                Err(_) => {
+                    self.msg = format!("Invalid pattern: {}", pattern);
+                    return
+                }
+            }
+        }
+        self.jump_to_match(false);
+    }
+
+    /// Moves the cursor to the next match of the last search,
+    /// going the opposite way from the search if `reverse`.
+    fn jump_to_match(&mut self, reverse: bool) {
+        let search = match &self.search {
+            Some(search) => search,
+            None => {
+                self.msg = "No previous search pattern".to_string();
+                return
+            }
+        };
+
+        let backward = search.backward != reverse;
+        match self.table.find(self.c, backward, |cell| search.is_match(cell)) {
+            Some((c, wrapped)) => {
+                self.c = c;
+                if wrapped && backward {
+                    self.msg = "search hit TOP, continuing at BOTTOM".to_string();
+                } else if wrapped {
+                    self.msg = "search hit BOTTOM, continuing at TOP".to_string();
+                }
+            }
+            None => self.msg = format!("Pattern not found: {}", search.pattern()),
+        }
+    }
+
+    /// Why the action can't be done, if it can't.
+    fn refusal(&self, action: &Action) -> Option<&'static str> {
+        if self.read_only && action.modifies() {
+            Some(READ_ONLY)
+        } else if self.table.is_streamed() && action.reshapes() {
+            Some(NOT_STREAMED)
+        } else {
+            None
+        }
+    }
+
     fn do_action(&mut self, action: Action) {
         use Action::*;
+        if let Some(msg) = self.refusal(&action) {
+            self.msg = msg.to_string();
+            return
+        }
         match action {
-            MoveCursor(dir) => {
-                self.c.move_dir(dir, &self.table);
+            MoveCursor(dir, n) => {
+                self.c.move_dir(dir, n, &self.table);
                 self.draw_table();
             }
             EnterPrompt(p) => {
@@ -69,7 +252,7 @@ impl State {
                         self.draw_prompt(p);
                     }
                     _ => {
-                        self.set_buf(&String::new());
+                        self.set_buf("");
                         self.draw_prompt(p);
                     }
                 }
@@ -88,7 +271,7 @@ impl State {
                 PromptAction::Submit => {
                     if let PromptType::EditReplace = p_type {
                         let b = self.take_buf();
-                        self.table.update(self.c, b);
+                        self.replace_value(b);
 
                     } else if let PromptType::EditAppend = p_type {
                         let b = self.take_buf();
@@ -96,39 +279,66 @@ impl State {
 
                     } else if let PromptType::Command = p_type {
                         for action in input::from_prompt(self.take_buf()) {
+                            // a refused write stops the quit in :wq
+                            if let Some(msg) = self.refusal(&action) {
+                                self.msg = msg.to_string();
+                                break
+                            }
                             self.do_action(action);
                         }
+
+                    } else if let PromptType::SearchForward | PromptType::SearchBackward = p_type {
+                        let b = self.take_buf();
+                        self.start_search(&b, matches!(p_type, PromptType::SearchBackward));
+
+                    } else if let PromptType::Fill = p_type {
+                        let b = self.take_buf();
+                        if let Some((tl, br)) = self.selected() {
+                            self.table.update_range(tl, br, self.c, |_| b.clone());
+                        }
+
+                    } else if let PromptType::AppendEach = p_type {
+                        let b = self.take_buf();
+                        if let Some((tl, br)) = self.selected() {
+                            self.table.update_range(tl, br, self.c, |v| format!("{}{}", v, b));
+                        }
                     }
                     self.r.clear_prompt();
                     if let Mode::Prompt(_) = self.m {
                         self.m = Mode::Table;
+                        self.selection = None;
                     }
                     self.draw_table();
                 }
                 PromptAction::Exit => {
                     self.r.clear_prompt();
                     self.m = Mode::Table;
+                    self.selection = None;
+                    self.draw_table();
                 }
             }
             ClearCell => self.table.clear(self.c),
 
             AddRowBelow => {
                 self.table.add_row_after(self.c);
-                self.c.move_dir(Dir::Down, &self.table);
+                self.c.move_dir(Dir::Down, 1, &self.table);
                 self.draw_table();
             }
             AddRowAbove => {
                 self.table.add_row_before(self.c);
                 self.draw_table();
             }
-            DeleteRow => {
-                self.table.delete_row(&mut self.c);
+            DeleteRow(n) => {
+                self.table.begin_group(self.c);
+                let rows = self.table.delete_rows(&mut self.c, n);
+                self.table.end_group();
+                self.registers.set(None, Register::Rows(rows));
                 self.draw_table();
             }
 
             AddColLeft => {
                 self.table.add_col_before(self.c);
-                self.c.move_dir(Dir::Left, &self.table);
+                self.c.move_dir(Dir::Left, 1, &self.table);
                 self.draw_table();
             }
             AddColRight => {
@@ -136,11 +346,126 @@ impl State {
                 self.draw_table();
             }
             DeleteCol => {
-                self.table.delete_col(&mut self.c);
+                let col = self.table.delete_col(&mut self.c);
+                self.registers.set(None, Register::Cols(vec![col]));
+                self.draw_table();
+            }
+
+            Yank(reg, object, n) => {
+                let (w, h) = self.table.dims();
+                let (r, msg) = match object {
+                    Object::Cell => (Register::Cell(self.table.get(self.c)), "cell yanked".to_string()),
+                    Object::Selection => match (self.selection, self.selected()) {
+                        (Some(sel), Some((tl, br))) => {
+                            let rows: Vec<Vec<String>> = (tl.y..=br.y)
+                                .map(|y| self.table.row(y)[tl.x..=br.x].to_vec())
+                                .collect();
+                            let msg = format!("{} by {} cells yanked", rows.len(), br.x - tl.x + 1);
+                            let r = match sel.kind {
+                                Visual::Block => Register::Block(rows),
+                                Visual::Rows => Register::Rows(rows),
+                                Visual::Cols => Register::Cols(
+                                    (tl.x..=br.x).map(|x| self.table.col(x)).collect()
+                                ),
+                            };
+                            (r, msg)
+                        }
+                        _ => return,
+                    },
+                    Object::Row => {
+                        let rows: Vec<_> = (self.c.y..h.min(self.c.y.saturating_add(n))).map(|y| self.table.row(y)).collect();
+                        let msg = format!("{} rows yanked", rows.len());
+                        (Register::Rows(rows), msg)
+                    }
+                    Object::Col => {
+                        let cols: Vec<_> = (self.c.x..w.min(self.c.x.saturating_add(n))).map(|x| self.table.col(x)).collect();
+                        let msg = format!("{} columns yanked", cols.len());
+                        (Register::Cols(cols), msg)
+                    }
+                };
+                self.registers.set(reg, r);
+                self.msg = msg;
+            }
+            Put(reg, before, n) => {
+                match self.registers.get(reg).cloned() {
+                    Some(Register::Rows(_)) | Some(Register::Cols(_)) if self.table.is_streamed() => {
+                        self.msg = NOT_STREAMED.to_string();
+                    }
+                    Some(Register::Cell(v)) => self.table.update(self.c, v),
+                    Some(Register::Rows(rows)) => match rows.len().checked_mul(n) {
+                        Some(len) => {
+                            if !before {
+                                self.c.y += 1;
+                            }
+                            // copies go one after another, not row by row
+                            let copies: Vec<_> = rows.iter().cycle().take(len).cloned().collect();
+                            self.table.insert_rows(self.c.y, &copies, self.c);
+                        }
+                        None => self.msg = TOO_MANY_COPIES.to_string(),
+                    }
+                    Some(Register::Cols(cols)) => match cols.len().checked_mul(n) {
+                        Some(len) => {
+                            if !before {
+                                self.c.x += 1;
+                            }
+                            let copies: Vec<_> = cols.iter().cycle().take(len).cloned().collect();
+                            self.table.insert_cols(self.c.x, &copies, self.c);
+                        }
+                        None => self.msg = TOO_MANY_COPIES.to_string(),
+                    }
+                    Some(Register::Block(rows)) => self.table.overwrite(self.c, &rows),
+                    None => self.msg = format!("Nothing in register {}", reg.unwrap_or('"')),
+                }
                 self.draw_table();
             }
 
-            EnterMode(m) => self.m = m,
+            EnterMode(m) => {
+                // everything typed in insert mode is undone together
+                if let Mode::Insert = m {
+                    self.table.begin_group(self.c);
+                } else if let Mode::Insert = self.m {
+                    self.table.end_group();
+                }
+
+                // switching between kinds of selection keeps where it started
+                if let Mode::Visual(kind) = m {
+                    let start = self.selection.map_or(self.c, |sel| sel.start);
+                    self.selection = Some(Selection::new(start, kind));
+                } else {
+                    self.selection = None;
+                }
+                self.m = m;
+                self.draw_table();
+            }
+
+            ClearSelection => {
+                if let Some((tl, br)) = self.selected() {
+                    self.table.update_range(tl, br, self.c, |_| String::new());
+                }
+                self.selection = None;
+                self.m = Mode::Table;
+                self.draw_table();
+            }
+            DeleteSelection => {
+                if let (Some(sel), Some((tl, br))) = (self.selection, self.selected()) {
+                    self.table.begin_group(self.c);
+                    if let Visual::Cols = sel.kind {
+                        let mut at = Cursor { x: tl.x, y: self.c.y };
+                        let cols = (tl.x..=br.x).map(|_| self.table.delete_col(&mut at)).collect();
+                        self.registers.set(None, Register::Cols(cols));
+                    } else {
+                        let mut at = Cursor { x: self.c.x, y: tl.y };
+                        let rows = self.table.delete_rows(&mut at, br.y - tl.y + 1);
+                        self.registers.set(None, Register::Rows(rows));
+                    }
+                    self.table.end_group();
+                    self.c = tl;
+                    self.c.clamp(&self.table);
+                }
+                self.selection = None;
+                self.m = Mode::Table;
+                self.draw_table();
+            }
 
             Append(c) => {
                 let mut val = self.table.get(self.c);
@@ -160,41 +485,407 @@ impl State {
                 if self.c.y == self.table.dims().1 - 1 {
                     self.do_action(Action::AddRowBelow);
                 }
-                self.do_action(Action::MoveCursor(Dir::Start));
-                self.do_action(Action::MoveCursor(Dir::Down));
+                self.do_action(Action::MoveCursor(Dir::Start, 1));
+                self.do_action(Action::MoveCursor(Dir::Down, 1));
                 self.draw_table();
             }
 
-            Save => self.table.save_to_path(self.path.to_string()),
-            Quit => self.m = Mode::Exit,
+            Undo(n) => {
+                for _ in 0..n {
+                    match self.table.undo() {
+                        Some(c) => self.c = c,
+                        None => break,
+                    }
+                }
+                self.c.clamp(&self.table);
+                self.draw_table();
+            }
+            Redo(n) => {
+                for _ in 0..n {
+                    match self.table.redo() {
+                        Some(c) => self.c = c,
+                        None => break,
+                    }
+                }
+                self.c.clamp(&self.table);
+                self.draw_table();
+            }
+
+            SearchNext => {
+                self.jump_to_match(false);
+                self.draw_table();
+            }
+            SearchPrev => {
+                self.jump_to_match(true);
+                self.draw_table();
+            }
+
+            Substitute(sub) => {
+                let (replaced, cells) = sub.apply(&mut self.table, self.c);
+                self.msg = if replaced == 0 {
+                    "Pattern not found".to_string()
+                } else {
+                    format!("{} substitutions in {} cells", replaced, cells)
+                };
+                self.draw_table();
+            }
+
+            Sort(sort) => {
+                if let Err(e) = sort.apply(&mut self.table, self.c) {
+                    self.msg = e;
+                }
+                self.draw_table();
+            }
+
+            Filter(filter) => {
+                match filter.apply(&mut self.table, self.c) {
+                    Ok(n) => self.msg = format!("{} rows match", n),
+                    Err(e) => self.msg = e,
+                }
+                self.c.clamp(&self.table);
+                self.draw_table();
+            }
+            Unfilter => {
+                self.table.unfilter();
+                self.c.clamp(&self.table);
+                self.draw_table();
+            }
+
+            Freeze(n) => {
+                self.frozen = n;
+                self.draw_table();
+            }
+            ColWidth(col, max) => {
+                let x = match col {
+                    Column::Cursor => Ok(self.c.x),
+                    Column::Named(name) => self.table.find_col(&name).ok_or(name),
+                };
+                match (x, max) {
+                    (Ok(x), Some(max)) => self.table.set_col_max(x, max),
+                    (Ok(x), None) => self.table.reset_col_max(x),
+                    (Err(name), _) => self.msg = format!("No column named {}", name),
+                }
+                self.draw_table();
+            }
+            MaxWidth(max) => {
+                self.table.set_max_width(max);
+                self.draw_table();
+            }
+            ToggleDetail => {
+                self.detail = !self.detail;
+                self.draw_table();
+            }
+
+            Set(key, value) => {
+                let mut dialect = self.table.dialect();
+                match dialect.set(&key, &value) {
+                    Ok(()) => {
+                        self.table.set_dialect(dialect);
+                        self.msg = dialect.to_string();
+                    }
+                    Err(e) => self.msg = e,
+                }
+            }
+            ShowSettings => self.msg = self.table.dialect().to_string(),
+            Reload if self.path.is_empty() => self.msg = "No file to read again".to_string(),
+            Reload => {
+                match self.table.reload(&self.path) {
+                    Ok(()) => {
+                        self.selection = None;
+                        self.c.clamp(&self.table);
+                        self.msg = format!("\"{}\" reloaded", self.path);
+                    }
+                    Err(e) => self.msg = format!("Error reading \"{}\": {}", self.path, e),
+                }
+                self.r.clear_screen();
+                self.draw_table();
+            }
+
+            Save if self.to_stdout => {
+                let mut bytes = Vec::new();
+                self.msg = match self.table.save_to(&mut bytes) {
+                    Ok(()) => {
+                        self.output = Some(bytes);
+                        "Written to stdout on exit".to_string()
+                    }
+                    Err(e) => format!("Error writing to stdout: {}", e),
+                };
+            }
+            Save => {
+                self.msg = match self.table.save_to_path(&self.path) {
+                    Ok(()) => format!("\"{}\" written", self.path),
+                    Err(e) => format!("Error writing \"{}\": {}", self.path, e),
+                };
+            }
+            Export(path) if self.read_only && same_file(&path, &self.path) => {
+                self.msg = READ_ONLY.to_string();
+            }
+            Export(path) => {
+                self.msg = match self.table.write_to_path(&path) {
+                    Ok(()) => format!("\"{}\" written", path),
+                    Err(e) => format!("Error writing \"{}\": {}", path, e),
+                };
+            }
+            SaveAs(path) => {
+                self.msg = match self.table.save_to_path(&path) {
+                    Ok(()) => {
+                        self.path = path;
+                        format!("\"{}\" written", self.path)
+                    }
+                    Err(e) => format!("Error writing \"{}\": {}", path, e),
+                };
+            }
+            Quit => {
+                // keep an earlier error, such as from a failed :wq
+                if !self.table.is_modified() {
+                    self.m = Mode::Exit;
+                } else if self.msg.is_empty() {
+                    self.msg = "No write since last change (add ! to override)".to_string();
+                }
+            }
+            ForceQuit => self.m = Mode::Exit,
+
+            GoToRow(y) => {
+                self.c.y = y;
+                self.c.clamp(&self.table);
+                self.draw_table();
+            }
+
+            Resize => {
+                self.r.clear_screen();
+                self.draw_table();
+                if let Mode::Prompt(p) = self.m {
+                    self.draw_prompt(p);
+                }
+            }
+
+            Error(e) => self.msg = e,
         }
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+const USAGE: &str = "usage: cic [options] <file>
+       cic view [options] <file>
+       cic [options] -
+
+options:
+  -d, --delimiter <char>   separates fields, like tab or ;
+      --quote <char>       quotes fields, \" by default
+      --escape <char>      escapes quotes in fields, or double to double them
+      --terminator <end>   ends records: lf, crlf or a character
+      --trim               trims whitespace around fields
+      --no-header          treats the first row as data, not column names
+      --max-width <n>      widest a column is shown, or 0 for no limit
+      --stream             reads rows as they're needed, as for files over 256 MiB
+  -R, --read-only          refuses changes, as does cic view
+      --stdout             writes what is saved to stdout on exit, not to the file
+      --columns <a,b,c>    names the columns of a new file
+
+A file of - , or none with stdin piped, reads the csv from stdin.  Keys
+are still read from the terminal, and saving writes to stdout as when
+stdout is piped.
+
+The delimiter and terminator are guessed from the file when not given.";
+
+/// What was given on the command line.
+struct Args {
+    path: Option<String>,
+    /// Dialect settings, applied over what is guessed from the file
+    settings: Vec<(String, String)>,
+    header: bool,
+    max_width: Option<Option<usize>>,
+    /// Whether to read rows as they're needed, whatever the size of the file
+    stream: bool,
+    read_only: bool,
+    stdout: bool,
+    /// Names of the columns of a new file
+    columns: Option<Vec<String>>,
+}
 
-    if args.len() < 2 {
-        println!("Enter a csv to edit");
-        return
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        path: None,
+        settings: Vec::new(),
+        header: true,
+        max_width: None,
+        stream: false,
+        read_only: false,
+        stdout: false,
+        columns: None,
+    };
+    let mut args = args.peekable();
+    // `cic view` opens the file read-only
+    if args.peek().is_some_and(|a| a == "view") {
+        parsed.read_only = true;
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let key = match flag.as_str() {
+            "--max-width" => {
+                let value = inline
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("Missing value for {}", flag))?;
+                let max = value.parse().map_err(|_| format!("Not a width: {}", value))?;
+                parsed.max_width = Some(if max == 0 { None } else { Some(max) });
+                continue
+            }
+            "--columns" => {
+                let value = inline
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("Missing value for {}", flag))?;
+                parsed.columns = Some(value.split(',').map(|c| c.trim().to_string()).collect());
+                continue
+            }
+            "-d" | "--delimiter" => "delimiter",
+            "--quote" => "quote",
+            "--escape" => "escape",
+            "--terminator" => "terminator",
+            "--trim" => {
+                parsed.settings.push(("trim".to_string(), String::new()));
+                continue
+            }
+            "--no-header" => {
+                parsed.header = false;
+                continue
+            }
+            "--stream" => {
+                parsed.stream = true;
+                continue
+            }
+            "-R" | "--read-only" => {
+                parsed.read_only = true;
+                continue
+            }
+            "--stdout" => {
+                parsed.stdout = true;
+                continue
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("Unknown option: {}", flag))
+            }
+            _ => {
+                if parsed.path.is_some() {
+                    return Err(format!("Unexpected argument: {}", arg));
+                }
+                parsed.path = Some(arg);
+                continue
+            }
+        };
+        let value = inline
+            .or_else(|| args.next())
+            .ok_or_else(|| format!("Missing value for {}", flag))?;
+        parsed.settings.push((key.to_string(), value));
     }
+    Ok(parsed)
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        // asking for help isn't an error
+        Err(e) if e.is_empty() => {
+            println!("{}", USAGE);
+            return
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
 
-    let filename = &args[1];
+    let new = args.path.as_deref().is_some_and(|p| p != "-" && !Path::new(p).exists());
+    if args.columns.is_some() && !new {
+        eprintln!("--columns only names the columns of a new file\n\n{}", USAGE);
+        process::exit(2);
+    }
 
-    let mut s = State::from_path(filename);
+    let loaded = match args.path.as_deref() {
+        Some("-") => from_stdin(&args),
+        Some(path) => State::from_path(path, with_settings(Dialect::detect(path), &args), &args)
+            .map_err(|e| format!("Error reading \"{}\": {}", path, e)),
+        None if !io::stdin().is_tty() => from_stdin(&args),
+        None => {
+            println!("Enter a csv to edit");
+            return
+        }
+    };
+    let mut s = match loaded {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
     s.draw_table();
-    s.r.draw_status(s.m);
+    s.draw_status();
 
+    let mut keys = input::Input::new();
     loop {
-        let actions = input::get_actions(s.m);
+        // keep drawing rows as a streamed file is read, until it's done
+        if s.loading && !keys.ready(LOADING_REFRESH) {
+            s.draw_table();
+            s.draw_status();
+            if let Some(e) = s.table.load_error().filter(|_| !s.loading) {
+                s.msg = format!("Error reading \"{}\": {}", s.path, e);
+                s.draw_status();
+            }
+            continue
+        }
+
+        // counts past the size of the table repeat nothing more
+        let (w, h) = s.table.dims();
+        let actions = keys.get_actions(s.m, w.max(h));
+        s.msg.clear();
         for action in actions {
             s.do_action(action);
         }
 
-        s.r.draw_status(s.m);
+        s.draw_status();
 
         if let Mode::Exit = s.m {
             break
         }
     }
+
+    // the terminal is given back before anything is written to stdout
+    let (to_stdout, output) = (s.to_stdout, s.output.take());
+    drop(s);
+    if to_stdout {
+        match output {
+            Some(bytes) => {
+                if let Err(e) = io::stdout().write_all(&bytes) {
+                    eprintln!("Error writing to stdout: {}", e);
+                    process::exit(1);
+                }
+            }
+            // quitting without saving leaves the rest of a pipeline nothing
+            None => process::exit(1),
+        }
+    }
+}
+
+/// Applies the dialect settings given on the command line over the guess.
+fn with_settings(mut dialect: Dialect, args: &Args) -> Dialect {
+    for (key, value) in args.settings.iter() {
+        if let Err(e) = dialect.set(key, value) {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+    dialect
+}
+
+/// Reads the whole csv from stdin, guessing its dialect from the first lines.
+fn from_stdin(args: &Args) -> Result<State, String> {
+    let mut bytes = Vec::new();
+    io::stdin()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Error reading stdin: {}", e))?;
+    let dialect = with_settings(Dialect::detect_bytes(&bytes), args);
+    State::from_bytes(&bytes, dialect, args).map_err(|e| format!("Error reading stdin: {}", e))
 }
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+/// The name of the register used when none is given.
+pub const UNNAMED: char = '"';
+
+/// What was yanked or deleted.
+#[derive(Clone, Debug)]
+pub enum Register {
+    Cell(String),
+    Rows(Vec<Vec<String>>),
+    Cols(Vec<Vec<String>>),
+    /// Rows of cells from a visual block
+    Block(Vec<Vec<String>>),
+}
+
+/// Which part of the table to yank.
+#[derive(Clone, Copy, Debug)]
+pub enum Object {
+    Cell,
+    Row,
+    Col,
+    Selection,
+}
+
+/// Named registers for yanking and putting, like vim's.
+#[derive(Debug, Default)]
+pub struct Registers {
+    map: HashMap<char, Register>,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the register, or the unnamed register if there is no name.
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        self.map.get(&name.unwrap_or(UNNAMED))
+    }
+
+    /// Stores into the register, and always the unnamed register too.
+    pub fn set(&mut self, name: Option<char>, r: Register) {
+        if let Some(name) = name {
+            self.map.insert(name, r.clone());
+        }
+        self.map.insert(UNNAMED, r);
+    }
+}
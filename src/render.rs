@@ -1,74 +1,245 @@
 use crossterm::{cursor, execute, QueueableCommand};
+use crossterm::tty::IsTty;
 use crossterm::style::{Print, Stylize, Attribute, SetAttribute};
 use crossterm::terminal::{self, ClearType, Clear, enable_raw_mode, disable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
 
-use crate::{Mode, Table, PromptType, Cursor};
+use crate::{Mode, Table, PromptType, Cursor, Search};
+use crate::text;
 
-use std::io::{self, Write};
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
 use std::ops::Drop;
 
+/// How a piece of the table is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Style {
+    Plain,
+    Cursor,
+    Selected,
+    Match,
+}
+
+/// Text drawn from a column of the screen.
+#[derive(Clone, Debug, PartialEq)]
+struct Segment {
+    x: usize,
+    text: String,
+    style: Style,
+}
+
+/// A line of the table as drawn: its segments from left to right,
+/// and whether a `>` marks columns hidden to the right.
+#[derive(Clone, Debug, PartialEq)]
+struct Line {
+    segments: Vec<Segment>,
+    more: bool,
+}
+
 pub struct Renderer {
-    stdout: io::Stdout,
+    /// The terminal, which is stdout unless it is piped elsewhere
+    out: Box<dyn Write>,
+    /// The lines of the table on screen, or `None` if they aren't known
+    frame: Option<Vec<Line>>,
+    /// Rows at the bottom of the table covered by the detail pane
+    pane: usize,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Renderer {
     
     /// Enables raw mode and enter alternate screen
+    /// Draws on the terminal directly if stdout is piped, as with `--stdout`.
     pub fn new() -> Renderer {
-        let mut stdout = io::stdout();
+        let mut out: Box<dyn Write> = if io::stdout().is_tty() {
+            Box::new(io::stdout())
+        } else {
+            Box::new(BufWriter::new(OpenOptions::new().write(true).open("/dev/tty").unwrap()))
+        };
         enable_raw_mode().unwrap();
-        execute!(stdout, cursor::Hide, EnterAlternateScreen).unwrap();
-        Renderer{ stdout }
+        execute!(out, cursor::Hide, EnterAlternateScreen).unwrap();
+        Renderer { out, frame: None, pane: 0 }
     }
 
-    /// Draws the table to the screen, highlights the cell the cursor is located
-    pub fn draw_table(&mut self, table: &Table, cur: &Cursor, anchor: &Cursor) {
-        self.stdout.queue(cursor::MoveTo(0, 0)).unwrap();
+    /// Keeps the table out of the bottom `rows` of where it is drawn.
+    pub fn set_pane(&mut self, rows: usize) {
+        if rows != self.pane {
+            self.pane = rows;
+            self.frame = None;
+        }
+    }
 
+    /// Draws the table to the screen, highlights the cell the cursor is located
+    /// The header row, or column numbers if the table has no header,
+    /// and the first `frozen` columns stay in place, the rest
+    /// are drawn from the anchor for as many as fit on the terminal,
+    /// with `<` and `>` marking columns hidden to either side.
+    /// Cells matching the search and cells between the `selected` corners
+    /// are highlighted.
+    /// Only what changed since the table was last drawn is redrawn.
+    pub fn draw_table(
+        &mut self,
+        table: &Table,
+        cur: &Cursor,
+        anchor: &Cursor,
+        frozen: usize,
+        search: Option<&Search>,
+        selected: Option<(Cursor, Cursor)>,
+    ) {
         let (w, h) = table.dims();
+        let (term_w, term_h) = terminal::size().unwrap();
+
+        // only the rows that fit above the pane, status bar and prompt are formatted
+        let first = table.body_start();
+        let top = anchor.y.max(first);
+        let bottom = h.min(top + (term_h as usize).saturating_sub(3 + self.pane).max(1));
+        table.load_rows(top..bottom);
 
-        let pretty = table.fmt_iter();
-        for (r, row) in pretty.iter().enumerate().skip(anchor.y) {
-            self.queue_row_index(r, h);
-            for (c, cell) in row.iter().enumerate() {
-                self.stdout
-                    .queue(
-                        if cur.y == r && cur.x == c {
-                            Print(format!("{} ", cell).negative())
-                        } else {
-                            Print(format!("{} ", cell).stylize())
-                        }
-                    )
-                    .unwrap()
-                    .queue(Print(" "))
-                    .unwrap();
+        let start = scroll_start(anchor.x, frozen);
+        let shown = visible_cols(table, frozen, start, term_w);
+        let hidden_left = start > frozen;
+        let hidden_right = start + shown < w;
+        let widths = table.col_widths();
+        // without a header, column numbers are shown in its place
+        let names = if table.has_header() { None } else { Some(table.fmt_names(&widths)) };
+        let rows = (0..first).chain(top..bottom).map(|r| (Some(r), table.fmt_row(r, &widths)));
+
+        let mut frame = Vec::new();
+        for (r, row) in names.into_iter().map(|row| (None, row)).chain(rows) {
+            let index = row_index(r, h, hidden_left);
+            let mut x = text::width(&index);
+            let mut segments = vec![Segment { x: 0, text: index, style: Style::Plain }];
+            let mut room = cells_width(table, term_w);
+            let cells = row.iter().enumerate().take(frozen)
+                .chain(row.iter().enumerate().skip(start).take(shown));
+            for (c, cell) in cells {
+                // a single column wider than the screen is cut off
+                let text = text::truncate(&format!("{} ", cell), room);
+                let width = text::width(&text);
+                room -= width;
+                let style = match r {
+                    None => Style::Plain,
+                    Some(r) if cur.y == r && cur.x == c => Style::Cursor,
+                    Some(r) if selected.is_some_and(|(tl, br)| {
+                        (tl.x..=br.x).contains(&c) && (tl.y..=br.y).contains(&r)
+                    }) => Style::Selected,
+                    Some(r) if search.is_some_and(|s| s.is_match(&table.get(Cursor { x: c, y: r }))) => {
+                        Style::Match
+                    }
+                    Some(_) => Style::Plain,
+                };
+                segments.push(Segment { x, text, style });
+                x += width;
+                if room > 0 {
+                    segments.push(Segment { x, text: " ".to_string(), style: Style::Plain });
+                    x += 1;
+                    room -= 1;
+                }
             }
-            self.stdout
-                .queue(Clear(ClearType::UntilNewLine))
-                .unwrap()
-                .queue(cursor::MoveDown(1))
+            frame.push(Line { segments, more: hidden_right });
+        }
+        self.paint(frame, term_w);
+    }
+
+    /// Draws the lines of the table that changed since it was last drawn.
+    /// Lines laid out the same as before only have their changed segments
+    /// redrawn, so moving the cursor redraws just two cells.
+    fn paint(&mut self, frame: Vec<Line>, term_w: u16) {
+        let old = self.frame.take();
+        for (y, line) in frame.iter().enumerate() {
+            let before = old.as_ref().and_then(|old| old.get(y));
+            match before {
+                Some(before) if before == line => {}
+                Some(before) if same_layout(before, line) => {
+                    let changed = before.segments.iter().zip(line.segments.iter()).filter(|(a, b)| a != b);
+                    for (_, segment) in changed {
+                        self.queue_segment(segment, y);
+                    }
+                }
+                _ => {
+                    for segment in line.segments.iter() {
+                        self.queue_segment(segment, y);
+                    }
+                    self.out.queue(Clear(ClearType::UntilNewLine)).unwrap();
+                    if line.more {
+                        self.out
+                            .queue(cursor::MoveTo(term_w - 1, y as u16))
+                            .unwrap()
+                            .queue(Print(">"))
+                            .unwrap();
+                    }
+                }
+            }
+        }
+
+        // clear where the table no longer reaches
+        if old.is_none_or(|old| old.len() > frame.len()) {
+            self.out
+                .queue(cursor::MoveTo(0, frame.len() as u16))
                 .unwrap()
-                .queue(cursor::MoveToColumn(0))
+                .queue(Clear(ClearType::FromCursorDown))
                 .unwrap();
         }
+        self.flush();
+        self.frame = Some(frame);
+    }
 
-        self.stdout
-            .queue(Clear(ClearType::FromCursorDown))
+    fn queue_segment(&mut self, segment: &Segment, y: usize) {
+        let text = segment.text.as_str();
+        self.out
+            .queue(cursor::MoveTo(segment.x as u16, y as u16))
             .unwrap()
-            .flush().unwrap();
+            .queue(match segment.style {
+                Style::Plain => Print(text.stylize()),
+                Style::Cursor => Print(text.negative()),
+                Style::Selected => Print(text.on_dark_grey()),
+                Style::Match => Print(text.black().on_yellow()),
+            })
+            .unwrap();
     }
 
-    fn queue_row_index(&mut self, i: usize, total_rows: usize) {
-        let w = format!("{}", total_rows).len();
-        self.stdout
-            .queue(Print(format!("{: <1$}  ", i, w)))
+    /// Draws the full value of a cell above the status bar, wrapped
+    /// to the screen, under a title naming the cell.
+    pub fn draw_detail(&mut self, title: &str, value: &str) {
+        let (w, last) = terminal::size().unwrap();
+        let w = (w as usize).saturating_sub(1);
+        let top = last.saturating_sub(2 + DETAIL_ROWS as u16);
+
+        let mut lines = text::wrap(value, w);
+        if lines.len() > DETAIL_ROWS - 1 {
+            lines.truncate(DETAIL_ROWS - 1);
+            let end = lines.last_mut().unwrap();
+            *end = text::fit(end, w.saturating_sub(1)) + "…";
+        }
+        lines.resize(DETAIL_ROWS - 1, String::new());
+
+        self.out
+            .queue(cursor::MoveTo(0, top))
+            .unwrap()
+            .queue(Print(format!(" {}", text::truncate(title, w)).bold()))
+            .unwrap()
+            .queue(Clear(ClearType::UntilNewLine))
             .unwrap();
+        for (i, line) in lines.iter().enumerate() {
+            self.out
+                .queue(cursor::MoveTo(0, top + 1 + i as u16))
+                .unwrap()
+                .queue(Print(format!(" {}", line)))
+                .unwrap()
+                .queue(Clear(ClearType::UntilNewLine))
+                .unwrap();
+        }
+        self.flush();
     }
 
     /// Draws at bottom of screen, exits raw mode, waits for input
     pub fn draw_prompt(&mut self, prompt: PromptType, buffer: &String) {
         let (_, last) = terminal::size().unwrap();
-        self.stdout
+        self.out
             .queue(cursor::MoveTo(0, last))
             .unwrap()
             .queue(Print(prompt.ps2()))
@@ -77,13 +248,13 @@ impl Renderer {
             .unwrap()
             .queue(Clear(ClearType::UntilNewLine))
             .unwrap();
-        self.stdout.flush().unwrap();
+        self.out.flush().unwrap();
     }
 
     /// Draws at bottom of screen, exits raw mode, waits for input
     pub fn clear_prompt(&mut self) {
         let (_, last) = terminal::size().unwrap();
-        self.stdout
+        self.out
             .queue(cursor::MoveTo(0, last-1))
             .unwrap()
             .queue(Clear(ClearType::CurrentLine))
@@ -92,17 +263,20 @@ impl Renderer {
     }
 
     pub fn clear_screen(&mut self) {
-        self.stdout
+        self.frame = None;
+        self.out
             .queue(Clear(ClearType::All))
             .unwrap();
         self.flush();
     }
 
-    pub fn draw_status(&mut self, mode: Mode) {
-        let msg = text_full_width(format!("{}", mode));
+    /// Draws the mode, flags such as [+] for unsaved changes, and a message
+    pub fn draw_status(&mut self, mode: Mode, flags: &[String], message: &str) {
+        let flags: String = flags.iter().map(|f| format!(" {}", f)).collect();
+        let msg = text_full_width(format!("{}{}  {}", mode, flags, message));
         let (_, last) = terminal::size().unwrap();
 
-        self.stdout
+        self.out
             .queue(cursor::MoveTo(0, last-2))
             .unwrap()
             .queue(SetAttribute(Attribute::Reverse))
@@ -117,19 +291,78 @@ impl Renderer {
     }
 
     fn flush(&mut self) {
-        self.stdout.flush().unwrap();
+        self.out.flush().unwrap();
     }
 }
 
 impl Drop for Renderer {
     fn drop(&mut self) {
         disable_raw_mode().unwrap();
-        execute!(self.stdout, cursor::Show, LeaveAlternateScreen).unwrap();
+        execute!(self.out, cursor::Show, LeaveAlternateScreen).unwrap();
     }
 }
 
-/// Returns a string padded to be the width of the terminal
+/// Rows taken by the cell detail pane, including its title.
+pub const DETAIL_ROWS: usize = 5;
+
+/// Returns a string padded, or cut, to be the width of the terminal
 fn text_full_width(s: String) -> String {
     let (w, _) = terminal::size().unwrap();
-    format!(" {: <1$}", s, w as usize - 1)
+    let w = (w as usize).saturating_sub(1);
+    format!(" {}", text::pad(&text::truncate(&s, w), w))
+}
+
+/// The row number, or a blank for the column numbers,
+/// padded to the width of the largest row number.
+fn row_index(i: Option<usize>, total_rows: usize, hidden_left: bool) -> String {
+    let w = format!("{}", total_rows).len();
+    let sep = if hidden_left { "< " } else { "  " };
+    let i = i.map_or(String::new(), |i| i.to_string());
+    format!("{: <1$}{2}", i, w, sep)
+}
+
+/// Whether two lines have the same segments in the same places,
+/// so the changed segments can be redrawn over the old ones.
+fn same_layout(a: &Line, b: &Line) -> bool {
+    a.more == b.more
+        && a.segments.len() == b.segments.len()
+        && a.segments.iter().zip(b.segments.iter()).all(|(a, b)| {
+            a.x == b.x && text::width(&a.text) == text::width(&b.text)
+        })
+}
+
+/// Width of the row numbers and the space after them
+fn gutter_width(table: &Table) -> usize {
+    let (_, h) = table.dims();
+    format!("{}", h).len() + 2
+}
+
+/// Width left for cells, keeping the last column free for the `>` marker
+fn cells_width(table: &Table, term_width: u16) -> usize {
+    (term_width as usize).saturating_sub(gutter_width(table) + 1)
+}
+
+/// First scrolling column drawn after the frozen ones
+pub(crate) fn scroll_start(anchor_x: usize, frozen: usize) -> usize {
+    anchor_x.max(frozen)
+}
+
+/// Returns how many columns, starting at `start`, fit on the screen
+/// after the first `frozen` columns have been drawn.
+/// At least one column is always counted, even if it must be cut off.
+pub(crate) fn visible_cols(table: &Table, frozen: usize, start: usize, term_width: u16) -> usize {
+    let widths = table.col_widths();
+    // each cell is padded by one space and followed by another
+    let frozen_width: usize = widths.iter().take(frozen).map(|w| *w as usize + 2).sum();
+    let room = cells_width(table, term_width).saturating_sub(frozen_width);
+    let mut used = 0;
+    let mut n = 0;
+    for w in widths.iter().skip(start) {
+        used += *w as usize + 2;
+        if used > room && n > 0 {
+            break
+        }
+        n += 1;
+    }
+    n
 }
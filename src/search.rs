@@ -0,0 +1,31 @@
+use regex::{Regex, RegexBuilder};
+
+/// A pattern being searched for in the table.
+/// Patterns are regular expressions, and a `\c` anywhere
+/// in the pattern makes the search case insensitive.
+#[derive(Debug)]
+pub struct Search {
+    re: Regex,
+    pattern: String,
+    pub backward: bool,
+}
+
+impl Search {
+    pub fn new(pattern: &str, backward: bool) -> Result<Self, regex::Error> {
+        let ignore_case = pattern.contains("\\c");
+        let re = RegexBuilder::new(&pattern.replace("\\c", ""))
+            .case_insensitive(ignore_case)
+            .build()?;
+        Ok(Self { re, pattern: pattern.to_string(), backward })
+    }
+
+    /// Whether the value of a cell matches the pattern.
+    pub fn is_match(&self, cell: &str) -> bool {
+        self.re.is_match(cell)
+    }
+
+    /// The pattern as it was typed.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{Cursor, Table};
+
+/// The shape of a visual mode selection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Visual {
+    /// The rectangle of cells between the start and the cursor
+    Block,
+    /// Every row between the start and the cursor
+    Rows,
+    /// Every column between the start and the cursor
+    Cols,
+}
+
+/// Cells selected in visual mode, from where it started to the cursor.
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    pub start: Cursor,
+    pub kind: Visual,
+}
+
+impl Selection {
+    pub fn new(start: Cursor, kind: Visual) -> Self {
+        Self { start, kind }
+    }
+
+    /// Returns the top left and bottom right cells of the selection,
+    /// inclusive, when the cursor is at `c`.
+    pub fn bounds(&self, c: Cursor, table: &Table) -> (Cursor, Cursor) {
+        let (w, h) = table.dims();
+        let mut start = self.start;
+        start.clamp(table);
+        let mut tl = Cursor { x: start.x.min(c.x), y: start.y.min(c.y) };
+        let mut br = Cursor { x: start.x.max(c.x), y: start.y.max(c.y) };
+        match self.kind {
+            Visual::Block => {}
+            Visual::Rows => {
+                tl.x = 0;
+                br.x = w - 1;
+            }
+            Visual::Cols => {
+                tl.y = 0;
+                br.y = h - 1;
+            }
+        }
+        (tl, br)
+    }
+}
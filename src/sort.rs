@@ -0,0 +1,159 @@
+use std::cmp::Ordering;
+
+use crate::{Cursor, Table};
+
+/// How two values in a column are compared.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compare {
+    /// Runs of digits compare as numbers, so `item2` comes before `item10`
+    Natural,
+    /// Character by character
+    Lexical,
+    /// As numbers, with values that aren't numbers after all that are
+    Numeric,
+}
+
+/// A column given to sort by.
+#[derive(Clone, Debug)]
+pub enum Column {
+    Cursor,
+    Named(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct SortKey {
+    col: Column,
+    desc: bool,
+    compare: Compare,
+}
+
+impl SortKey {
+    /// Parses a key like `price:num:desc`.
+    /// The column is a header, a column number or `.` for the cursor column.
+    /// Options are `asc`, `desc`, `nat`, `lex` and `num`.
+    fn parse(s: &str, desc: bool) -> Result<Self, String> {
+        let mut parts = s.split(':');
+        let col = match parts.next() {
+            Some(".") | Some("") | None => Column::Cursor,
+            Some(name) => Column::Named(name.to_string()),
+        };
+        let mut key = SortKey { col, desc, compare: Compare::Natural };
+        for opt in parts {
+            match opt {
+                "asc" => key.desc = false,
+                "desc" => key.desc = true,
+                "nat" => key.compare = Compare::Natural,
+                "lex" => key.compare = Compare::Lexical,
+                "num" => key.compare = Compare::Numeric,
+                _ => return Err(format!("Unknown sort option: {}", opt)),
+            }
+        }
+        Ok(key)
+    }
+}
+
+/// Sorts the rows below the header, if any, like `:sort` or `:sort date:desc id:num`.
+/// With no columns given, sorts by the cursor column.
+/// Rows that compare equal on every key keep their order.
+#[derive(Clone, Debug)]
+pub struct Sort {
+    keys: Vec<SortKey>,
+}
+
+impl Sort {
+    /// Parses the words after `:sort`, which sorts descending
+    /// by default when typed as `:sort!`.
+    pub fn parse(args: &[&str], desc: bool) -> Result<Self, String> {
+        let keys = if args.is_empty() {
+            vec![SortKey { col: Column::Cursor, desc, compare: Compare::Natural }]
+        } else {
+            args.iter().map(|a| SortKey::parse(a, desc)).collect::<Result<_, _>>()?
+        };
+        Ok(Self { keys })
+    }
+
+    /// Sorts the table as one undo step.
+    pub fn apply(&self, table: &mut Table, c: Cursor) -> Result<(), String> {
+        let mut cols = Vec::new();
+        for key in self.keys.iter() {
+            let x = match &key.col {
+                Column::Cursor => c.x,
+                Column::Named(name) => table
+                    .find_col(name)
+                    .ok_or_else(|| format!("No column named {}", name))?,
+            };
+            cols.push((x, key));
+        }
+
+        let rows = table.rows();
+        let start = table.body_start();
+        let mut order: Vec<usize> = (start..rows.len()).collect();
+        order.sort_by(|a, b| {
+            cols.iter()
+                .map(|(x, key)| {
+                    let o = compare(&rows[*a][*x], &rows[*b][*x], key.compare);
+                    if key.desc { o.reverse() } else { o }
+                })
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        // the header stays first
+        if table.has_header() {
+            order.insert(0, 0);
+        }
+        table.permute_rows(order, c);
+        Ok(())
+    }
+}
+
+pub fn compare(a: &str, b: &str, how: Compare) -> Ordering {
+    match how {
+        Compare::Lexical => a.cmp(b),
+        Compare::Natural => natural_cmp(a, b),
+        Compare::Numeric => match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+            (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => a.cmp(b),
+        },
+    }
+}
+
+/// Compares runs of digits by their value and everything else by character.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let n = take_digits(&mut a);
+                let m = take_digits(&mut b);
+                // without leading zeros, a longer run is a larger number
+                let o = n.len().cmp(&m.len()).then_with(|| n.cmp(&m));
+                if o != Ordering::Equal {
+                    return o;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_digits(s: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(d) = s.peek().copied().filter(|d| d.is_ascii_digit()) {
+        digits.push(d);
+        s.next();
+    }
+    let trimmed = digits.trim_start_matches('0');
+    trimmed.to_string()
+}
@@ -0,0 +1,410 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::dialect::BOM;
+use crate::text;
+use crate::{Dialect, Error, Result};
+
+/// Rows read at once when they are first needed.
+const CHUNK_ROWS: usize = 1000;
+
+/// Chunks of rows kept in memory, dropping the least recently used.
+const CACHED_CHUNKS: usize = 64;
+
+/// Rows found between updates to the index, so it is rarely locked.
+const INDEX_BATCH: usize = 10_000;
+
+/// Where each row starts in the file, found by reading it in the background.
+#[derive(Debug, Default)]
+struct Index {
+    offsets: Vec<u64>,
+    /// Bytes of the file read so far
+    read: u64,
+    done: bool,
+    /// Stops the reading early, once the rows are no longer wanted
+    cancelled: bool,
+    error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    chunks: HashMap<usize, Rc<Vec<Vec<String>>>>,
+    /// Chunks from least to most recently used
+    used: VecDeque<usize>,
+    /// Terminal columns needed for the widest value of each column read so far
+    widest: Vec<usize>,
+}
+
+/// Rows of a csv read from the file as they're needed rather than all at
+/// once, for files too large to hold in memory.  Edited rows are kept in
+/// memory until saved, and the rest are written as they were read.
+#[derive(Debug)]
+pub struct Stream {
+    path: String,
+    dialect: Dialect,
+    /// Where the rows start, after any byte order mark
+    start: u64,
+    size: u64,
+    index: Arc<Mutex<Index>>,
+    /// Fields in a row, from the widest of the first rows
+    width: usize,
+    /// Rows known before the index reaches them
+    first_rows: usize,
+    cache: RefCell<Cache>,
+    edited: HashMap<usize, Vec<String>>,
+}
+
+impl Stream {
+    /// Opens the csv at the path, reading its first rows now and finding
+    /// where the rest start in the background.  Returns the first rows too.
+    pub fn open(path: &str, dialect: Dialect) -> Result<(Self, Vec<Vec<String>>)> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut head = [0; 3];
+        let n = file.read(&mut head)?;
+        let start = if head[..n].starts_with(BOM) { BOM.len() as u64 } else { 0 };
+
+        file.seek(SeekFrom::Start(start))?;
+        let mut rdr = reader(dialect, file);
+        let mut rows = Vec::new();
+        let mut record = csv::StringRecord::new();
+        while rows.len() < CHUNK_ROWS && rdr.read_record(&mut record)? {
+            rows.push(record.iter().map(|x| x.to_owned()).collect::<Vec<String>>());
+        }
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0).max(1);
+        for row in rows.iter_mut() {
+            row.resize(width, String::new());
+        }
+
+        let index = Arc::new(Mutex::new(Index::default()));
+        let background = Arc::clone(&index);
+        let (p, d) = (path.to_string(), dialect);
+        thread::spawn(move || {
+            let result = build_index(&p, d, start, &background);
+            let mut index = background.lock().unwrap();
+            if let Err(e) = result {
+                index.error = Some(e.to_string());
+            }
+            index.done = true;
+        });
+
+        let mut cache = Cache { widest: vec![0; width], ..Cache::default() };
+        cache.chunks.insert(0, Rc::new(rows.clone()));
+        cache.used.push_back(0);
+        let stream = Stream {
+            path: path.to_string(),
+            dialect,
+            start,
+            size,
+            index,
+            width,
+            first_rows: rows.len(),
+            cache: RefCell::new(cache),
+            edited: HashMap::new(),
+        };
+        Ok((stream, rows))
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of rows found so far, which is never none.
+    pub fn len(&self) -> usize {
+        let index = self.index.lock().unwrap();
+        index.offsets.len().max(self.first_rows).max(1)
+    }
+
+    /// How much of the file has been read to find its rows, as a percent,
+    /// while it is still being read.
+    pub fn loading(&self) -> Option<u64> {
+        let index = self.index.lock().unwrap();
+        if index.done {
+            None
+        } else {
+            Some(index.read * 100 / self.size.max(1))
+        }
+    }
+
+    /// Terminal columns needed for the widest value of each column in the
+    /// rows read so far, which grow as more of the file is read.
+    pub fn widest(&self) -> Vec<usize> {
+        self.cache.borrow().widest.clone()
+    }
+
+    /// What went wrong reading the file in the background, if anything.
+    pub fn error(&self) -> Option<String> {
+        self.index.lock().unwrap().error.clone()
+    }
+
+    /// Gets a copy of a row, reading it from the file if needed.
+    pub fn row(&self, r: usize) -> Vec<String> {
+        if let Some(row) = self.edited.get(&r) {
+            return row.clone();
+        }
+        self.chunk(r / CHUNK_ROWS)
+            .get(r % CHUNK_ROWS)
+            .cloned()
+            .unwrap_or_else(|| vec![String::new(); self.width])
+    }
+
+    /// Changes a value, keeping the row in memory until it is saved.
+    pub fn update(&mut self, r: usize, x: usize, value: String) {
+        let mut row = self.row(r);
+        if x >= row.len() {
+            row.resize(x + 1, String::new());
+        }
+        row[x] = value;
+        self.edited.insert(r, row);
+    }
+
+    /// Gets a chunk of rows from the cache, or reads it from the file.
+    fn chunk(&self, k: usize) -> Rc<Vec<Vec<String>>> {
+        let mut cache = self.cache.borrow_mut();
+        if let Some(rows) = cache.chunks.get(&k).cloned() {
+            cache.used.retain(|c| *c != k);
+            cache.used.push_back(k);
+            return rows;
+        }
+
+        // rows not found yet, or not readable, are left to try again
+        let rows = match self.read_chunk(k) {
+            Ok(Some(rows)) => Rc::new(rows),
+            _ => return Rc::new(Vec::new()),
+        };
+        for row in rows.iter() {
+            for (w, cell) in cache.widest.iter_mut().zip(row) {
+                *w = (*w).max(text::width(cell));
+            }
+        }
+        if cache.used.len() >= CACHED_CHUNKS {
+            if let Some(old) = cache.used.pop_front() {
+                cache.chunks.remove(&old);
+            }
+        }
+        cache.chunks.insert(k, Rc::clone(&rows));
+        cache.used.push_back(k);
+        rows
+    }
+
+    fn read_chunk(&self, k: usize) -> Result<Option<Vec<Vec<String>>>> {
+        let offset = match self.index.lock().unwrap().offsets.get(k * CHUNK_ROWS) {
+            Some(offset) => *offset,
+            None => return Ok(None),
+        };
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut rdr = reader(self.dialect, BufReader::new(file));
+        let mut rows = Vec::new();
+        let mut record = csv::StringRecord::new();
+        while rows.len() < CHUNK_ROWS && rdr.read_record(&mut record)? {
+            let mut row: Vec<String> = record.iter().map(|x| x.to_owned()).collect();
+            if row.len() < self.width {
+                row.resize(self.width, String::new());
+            }
+            rows.push(row);
+        }
+        Ok(Some(rows))
+    }
+
+    /// Writes every row to the path in the dialect.
+    /// The file is written beside the path, then moved over it.
+    pub fn save(&self, path: &str, dialect: Dialect) -> Result<()> {
+        if self.loading().is_some() {
+            return Err(Error::Loading);
+        }
+        let tmp = format!("{}.tmp", path);
+        self.write(File::create(&tmp)?, dialect)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Writes every row to the writer in the dialect.  Rows that weren't
+    /// edited are copied from the file as they were if the dialect is the same.
+    pub fn write<W: Write>(&self, wtr: W, dialect: Dialect) -> Result<()> {
+        let offsets = {
+            let index = self.index.lock().unwrap();
+            if !index.done {
+                return Err(Error::Loading);
+            }
+            index.offsets.clone()
+        };
+
+        let mut src = File::open(&self.path)?;
+        let final_terminator = match self.size {
+            0 => true,
+            n => {
+                src.seek(SeekFrom::Start(n - 1))?;
+                let mut last = [0];
+                src.read_exact(&mut last)?;
+                self.dialect.is_terminator(last[0])
+            }
+        };
+        let terminator = dialect.terminator_bytes();
+
+        let mut wtr = BufWriter::new(wtr);
+        if dialect.bom {
+            wtr.write_all(BOM)?;
+        }
+        let mut src = BufReader::new(src);
+        src.seek(SeekFrom::Start(offsets.first().copied().unwrap_or(self.start)))?;
+        let mut raw = Vec::new();
+        for (i, start) in offsets.iter().enumerate() {
+            let end = offsets.get(i + 1).copied().unwrap_or(self.size);
+            raw.resize((end - start) as usize, 0);
+            src.read_exact(&mut raw)?;
+
+            match self.edited.get(&i) {
+                Some(row) => wtr.write_all(&dialect.write_row(row)?)?,
+                None if dialect == self.dialect => wtr.write_all(self.dialect.trim_terminators(&raw))?,
+                None => {
+                    let mut rdr = reader(self.dialect, &raw[..]);
+                    let mut record = csv::StringRecord::new();
+                    rdr.read_record(&mut record)?;
+                    let row: Vec<String> = record.iter().map(|x| x.to_owned()).collect();
+                    wtr.write_all(&dialect.write_row(&row)?)?;
+                }
+            }
+            if i + 1 < offsets.len() || final_terminator || dialect != self.dialect {
+                wtr.write_all(&terminator)?;
+            }
+        }
+        // an empty file still shows a row, which is only written if edited
+        if let (true, Some(row)) = (offsets.is_empty(), self.edited.get(&0)) {
+            wtr.write_all(&dialect.write_row(row)?)?;
+            wtr.write_all(&terminator)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if let Ok(mut index) = self.index.lock() {
+            index.cancelled = true;
+        }
+    }
+}
+
+fn reader<R: Read>(dialect: Dialect, rdr: R) -> csv::Reader<R> {
+    dialect.reader().has_headers(false).flexible(true).from_reader(rdr)
+}
+
+/// Finds where every row in the file starts, adding them to the index in batches.
+fn build_index(path: &str, dialect: Dialect, start: u64, index: &Mutex<Index>) -> Result<()> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut rdr = reader(dialect, BufReader::new(file));
+    let mut record = csv::ByteRecord::new();
+    let mut batch = Vec::with_capacity(INDEX_BATCH);
+    loop {
+        let more = rdr.read_byte_record(&mut record)?;
+        if let Some(p) = record.position().filter(|_| more) {
+            batch.push(start + p.byte());
+        }
+        if batch.len() == INDEX_BATCH || !more {
+            let mut index = index.lock().unwrap();
+            index.offsets.append(&mut batch);
+            index.read = start + rdr.position().byte();
+            if index.cancelled || !more {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    /// A file of its own in the temp directory, removed once done.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, bytes: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("cic-{}-{}.csv", std::process::id(), name));
+            fs::write(&path, bytes).unwrap();
+            TempFile(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Rows over a few chunks, some quoted and all ending in crlf.
+    fn lines(rows: usize) -> Vec<String> {
+        let mut lines = vec!["id,name".to_string()];
+        for i in 1..rows {
+            lines.push(if i % 3 == 0 { format!("{},\"row {}\"", i, i) } else { format!("{},row {}", i, i) });
+        }
+        lines
+    }
+
+    fn open(file: &TempFile) -> Stream {
+        let (stream, _) = Stream::open(file.path(), Dialect::detect(file.path())).unwrap();
+        while stream.loading().is_some() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        stream
+    }
+
+    #[test]
+    fn saves_an_edit_past_the_first_chunk() {
+        let mut lines = lines(CHUNK_ROWS * 2 + 500);
+        let file = TempFile::new("edit", (lines.join("\r\n") + "\r\n").as_bytes());
+        let mut stream = open(&file);
+        assert_eq!(stream.len(), lines.len());
+        assert_eq!(stream.row(1500), vec!["1500", "row 1500"]);
+
+        stream.update(1500, 1, "a, b".to_string());
+        lines[1500] = "1500,\"a, b\"".to_string();
+        // the same file, named another way
+        let dir = file.0.parent().unwrap().join(".");
+        let other = dir.join(file.0.file_name().unwrap());
+        stream.save(other.to_str().unwrap(), stream.dialect).unwrap();
+        assert_eq!(fs::read(&file.0).unwrap(), (lines.join("\r\n") + "\r\n").into_bytes());
+    }
+
+    #[test]
+    fn keeps_a_missing_final_terminator() {
+        let mut lines = lines(CHUNK_ROWS + 10);
+        let file = TempFile::new("final", lines.join("\n").as_bytes());
+        let mut stream = open(&file);
+
+        stream.update(CHUNK_ROWS + 9, 0, "last".to_string());
+        *lines.last_mut().unwrap() = format!("last,row {}", CHUNK_ROWS + 9);
+        let mut out = Vec::new();
+        stream.write(&mut out, stream.dialect).unwrap();
+        assert_eq!(out, lines.join("\n").into_bytes());
+    }
+
+    #[test]
+    fn writes_every_row_anew_in_another_dialect() {
+        let file = TempFile::new("dialect", b"a,b\n\"x\",y\n");
+        let stream = open(&file);
+
+        let mut out = Vec::new();
+        let dialect = Dialect { delimiter: b';', ..stream.dialect };
+        stream.write(&mut out, dialect).unwrap();
+        assert_eq!(out, b"a;b\nx;y\n");
+    }
+}
@@ -0,0 +1,171 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::{Cursor, Table};
+
+/// A row given in the range of a substitute command.
+#[derive(Clone, Copy, Debug)]
+pub enum Line {
+    Number(usize),
+    Current,
+    Last,
+}
+
+/// Which rows a substitute command changes.
+#[derive(Clone, Copy, Debug)]
+pub enum Rows {
+    Current,
+    All,
+    Between(Line, Line),
+}
+
+/// A find and replace command, like `:%s/teh/the/g`.
+///
+/// The range before the `s` is empty for the cursor row, `%` for every row,
+/// or `N` or `N,M` for row numbers, where `.` is the cursor row and `$` the last.
+/// Flags after the replacement are `g` to replace every match in a cell
+/// instead of the first, `i` to ignore case and `c` to only change the
+/// cursor column.  The replacement may use captures as `$1` or `${name}`.
+#[derive(Clone, Debug)]
+pub struct Substitute {
+    re: Regex,
+    rep: String,
+    rows: Rows,
+    global: bool,
+    column: bool,
+}
+
+impl Substitute {
+    /// Parses a substitute command typed at the prompt.
+    /// Returns `None` if it isn't a substitute command at all.
+    pub fn parse(s: &str) -> Option<Result<Self, String>> {
+        let s = s.trim();
+        let at = s.find('s')?;
+        let rows = parse_rows(&s[..at])?;
+
+        let rest = &s[at + 1..];
+        let delim = rest.chars().next()?;
+        if delim.is_alphanumeric() || delim.is_whitespace() || delim == '\\' {
+            return None;
+        }
+
+        let parts = split_delimited(&rest[delim.len_utf8()..], delim);
+        if parts.len() > 3 {
+            return Some(Err(format!("Trailing characters: {}", parts[3..].join(&delim.to_string()))));
+        }
+        let pattern = &parts[0];
+        let rep = parts.get(1).cloned().unwrap_or_default();
+        let flags = parts.get(2).cloned().unwrap_or_default();
+
+        let mut global = false;
+        let mut ignore_case = false;
+        let mut column = false;
+        for f in flags.chars() {
+            match f {
+                'g' => global = true,
+                'i' => ignore_case = true,
+                'c' => column = true,
+                _ => return Some(Err(format!("Unknown flag: {}", f))),
+            }
+        }
+
+        let re = match RegexBuilder::new(pattern).case_insensitive(ignore_case).build() {
+            Ok(re) => re,
+            Err(_) => return Some(Err(format!("Invalid pattern: {}", pattern))),
+        };
+
+        Some(Ok(Self { re, rep, rows, global, column }))
+    }
+
+    /// Replaces matches in the table as one undo step.
+    /// Returns the number of replacements and the number of cells changed.
+    pub fn apply(&self, table: &mut Table, c: Cursor) -> (usize, usize) {
+        let (w, h) = table.dims();
+        let (first, last) = match self.rows {
+            Rows::Current => (c.y, c.y),
+            Rows::All => (0, h - 1),
+            Rows::Between(a, b) => (line_number(a, c, h), line_number(b, c, h)),
+        };
+        let (first, last) = (first.min(last), first.max(last).min(h - 1));
+        let (left, right) = if self.column { (c.x, c.x) } else { (0, w - 1) };
+
+        let mut replaced = 0;
+        let mut cells = 0;
+        table.begin_group(c);
+        for y in first..=last {
+            for x in left..=right {
+                let at = Cursor { x, y };
+                let old = table.get(at);
+                let count = self.re.find_iter(&old).count();
+                if count == 0 {
+                    continue
+                }
+                let new = if self.global {
+                    self.re.replace_all(&old, self.rep.as_str())
+                } else {
+                    self.re.replace(&old, self.rep.as_str())
+                };
+                table.update(at, new.into_owned());
+                replaced += if self.global { count } else { 1 };
+                cells += 1;
+            }
+        }
+        table.end_group();
+        (replaced, cells)
+    }
+}
+
+/// Parses the range before the `s` of a substitute command.
+fn parse_rows(s: &str) -> Option<Rows> {
+    match s.trim() {
+        "" => Some(Rows::Current),
+        "%" => Some(Rows::All),
+        s => match s.split_once(',') {
+            Some((a, b)) => Some(Rows::Between(parse_line(a)?, parse_line(b)?)),
+            None => {
+                let l = parse_line(s)?;
+                Some(Rows::Between(l, l))
+            }
+        }
+    }
+}
+
+fn parse_line(s: &str) -> Option<Line> {
+    match s.trim() {
+        "." => Some(Line::Current),
+        "$" => Some(Line::Last),
+        n => n.parse().ok().map(Line::Number),
+    }
+}
+
+fn line_number(l: Line, c: Cursor, rows: usize) -> usize {
+    match l {
+        Line::Number(n) => n,
+        Line::Current => c.y,
+        Line::Last => rows - 1,
+    }
+}
+
+/// Splits on the delimiter, except where it is escaped with a backslash.
+/// Other backslashes are kept for the regex.
+fn split_delimited(s: &str, delim: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        let part = parts.last_mut().unwrap();
+        if ch == '\\' {
+            match chars.next() {
+                Some(next) if next == delim => part.push(next),
+                Some(next) => {
+                    part.push(ch);
+                    part.push(next);
+                }
+                None => part.push(ch),
+            }
+        } else if ch == delim {
+            parts.push(String::new());
+        } else {
+            part.push(ch);
+        }
+    }
+    parts
+}
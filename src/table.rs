@@ -1,90 +1,453 @@
-use csv;
+use crate::{same_file, Cursor, Dialect, Result};
+use crate::dialect::BOM;
+use crate::history::{Edit, History};
+use crate::stream::Stream;
+use crate::text;
+use crate::types::Type;
 
-use std::io::{self, Write};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
 
-use crate::{Cursor};
+/// The rows of a csv as they were read, so rows that
+/// haven't changed are saved exactly as they were.
+#[derive(Debug)]
+struct Original {
+    /// The dialect the rows were read in
+    dialect: Dialect,
+    /// The file as read, after any byte order mark
+    bytes: Vec<u8>,
+    /// Where each row read lies in `bytes`, without its terminator
+    spans: Vec<Range<usize>>,
+    /// How many fields every row was padded to
+    width: usize,
+    /// Whether the last row ended with a terminator
+    final_terminator: bool,
+}
+
+impl Original {
+    /// The bytes row `k` was read from, if reading them
+    /// again still gives the values of the row.
+    fn line<R>(&self, rdr: &mut csv::Reader<R>, k: usize, row: &[String]) -> Option<&[u8]>
+    where
+        R: io::Read + io::Seek,
+    {
+        let span = self.spans[k].clone();
+        let mut pos = csv::Position::new();
+        pos.set_byte(span.start as u64);
+        rdr.seek(pos).ok()?;
+        let mut record = csv::StringRecord::new();
+        rdr.read_record(&mut record).ok()?;
+        // fields past the end of a short row were padding, unless columns were added since
+        let same = row.len() == self.width
+            && record.len() <= row.len()
+            && record.iter().zip(row).all(|(a, b)| a == b)
+            && row[record.len()..].iter().all(|cell| cell.is_empty());
+        if same {
+            Some(&self.bytes[span])
+        } else {
+            None
+        }
+    }
+}
+
+/// Widest a column is drawn unless changed.
+pub const DEFAULT_MAX_WIDTH: usize = 40;
+
+/// Rows of a streamed file read to guess the types of its columns.
+const INFER_ROWS: usize = 1000;
 
 /// Contains the data read from a csv
 /// Assumes nonzero columns and rows
+///
+/// Rows may be filtered, hiding some from view.  Every row index
+/// taken or given by the table, like a cursor's, counts only the rows
+/// in view, while saving still writes every row.
 #[derive(Debug)]
 pub struct Table {
     data: Vec<Vec<String>>,
+    /// How many cells of each width every column has, counting rows out
+    /// of view, so the widest is known without reading every cell
+    widths: Vec<BTreeMap<usize, usize>>,
+    history: History,
+    modified: bool,
+    /// Indices into `data` of the rows in view, in order, if filtered
+    visible: Option<Vec<usize>>,
+    /// Whether the first row names the columns
+    header: bool,
+    /// What kind of values each column holds, guessed when read
+    types: Vec<Type>,
+    /// Widest any column is drawn, if limited
+    max_width: Option<usize>,
+    /// Widest particular columns are drawn, in place of `max_width`
+    col_max: HashMap<usize, Option<usize>>,
+    /// How the csv is read and written
+    dialect: Dialect,
+    original: Option<Original>,
+    /// For each row in `data`, the row of the original it was read as,
+    /// which is followed as rows are inserted, deleted and reordered
+    origin: Vec<Option<usize>>,
+    /// The file the rows are read from as needed, in place of `data`,
+    /// when it is too large to read at once
+    stream: Option<Stream>,
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Table {
     pub fn new() -> Self {
         Self {
             data: vec![vec![String::new()]],
+            widths: count_widths(&[vec![String::new()]]),
+            history: History::new(),
+            modified: false,
+            visible: None,
+            header: true,
+            types: vec![Type::Text],
+            max_width: Some(DEFAULT_MAX_WIDTH),
+            col_max: HashMap::new(),
+            dialect: Dialect::default(),
+            original: None,
+            origin: vec![None],
+            stream: None,
         }
     }
 
-    /// Construct new table from the csv at the provided path.
-    pub fn from_path(path: &str) -> Self {
-        let mut rdr = csv::Reader::from_path(path).unwrap();
+    /// Construct a table with only a header naming the columns, for a new file.
+    pub fn with_columns(names: Vec<String>, dialect: Dialect) -> Self {
+        let data = vec![names];
+        let widths = count_widths(&data);
+        let types = vec![Type::Text; data[0].len()];
+        let origin = vec![None; data.len()];
+        Table { data, widths, types, dialect, origin, ..Table::new() }
+    }
+
+    /// Construct new table from the csv at the provided path,
+    /// read in the given dialect, which is kept for saving.
+    /// A path that does not exist yet gives an empty table.
+    pub fn from_path(path: &str, dialect: Dialect) -> Result<Self> {
+        if !Path::new(path).exists() {
+            return Ok(Table { dialect, ..Table::new() });
+        }
+
+        Table::from_bytes(&fs::read(path)?, dialect)
+    }
+
+    /// Construct new table from the bytes of a csv, as read from stdin,
+    /// read in the given dialect, which is kept for saving.
+    pub fn from_bytes(bytes: &[u8], dialect: Dialect) -> Result<Self> {
+        let body = bytes.strip_prefix(BOM).unwrap_or(bytes);
+        // rows may have any number of fields
+        let mut rdr = dialect.reader().has_headers(false).flexible(true).from_reader(body);
         let mut data: Vec<Vec<String>> = Vec::new();
+        let mut starts = Vec::new();
 
-        let headers: Vec<String> = rdr
-            .headers()
-            .unwrap()
-            .into_iter()
-            .map(|x| x.to_string())
+        let mut record = csv::StringRecord::new();
+        while rdr.read_record(&mut record)? {
+            starts.extend(record.position().map(|p| p.byte() as usize));
+            data.push(record.iter().map(|x| x.to_owned()).collect());
+        }
+        if data.is_empty() {
+            data.push(vec![String::new()]);
+        }
+
+        // short rows are padded to the longest
+        let w = data.iter().map(|row| row.len()).max().unwrap_or(0).max(1);
+        for row in data.iter_mut() {
+            row.resize(w, String::new());
+        }
+
+        // each row runs until the next starts, with terminators either side
+        let ends = starts.iter().skip(1).copied().chain(Some(body.len()));
+        let spans = starts
+            .iter()
+            .zip(ends)
+            .map(|(start, end)| {
+                let line = &body[*start..end];
+                let from = start + line.iter().take_while(|b| dialect.is_terminator(**b)).count();
+                from..from + dialect.trim_terminators(line).len()
+            })
+            .collect();
+        let mut origin: Vec<Option<usize>> = (0..starts.len()).map(Some).collect();
+        origin.resize(data.len(), None);
+        let final_terminator = body.last().is_none_or(|b| dialect.is_terminator(*b));
+        let original = Original { dialect, bytes: body.to_vec(), spans, width: w, final_terminator };
+
+        let widths = count_widths(&data);
+        let mut table = Table { data, widths, dialect, original: Some(original), origin, ..Table::new() };
+        table.infer_types();
+        Ok(table)
+    }
+
+    /// Construct a table that reads rows from the csv at the path as they're
+    /// needed, finding where they are in the background.  Only values can be
+    /// changed, and columns widen as wider rows are read.
+    pub fn stream_path(path: &str, dialect: Dialect) -> Result<Self> {
+        let (stream, first) = Stream::open(path, dialect)?;
+        // an empty file has no rows to give the width, but still has a column
+        let mut widths = vec![BTreeMap::new(); stream.width()];
+        for row in first.iter() {
+            for (counts, cell) in widths.iter_mut().zip(row) {
+                count(counts, cell);
+            }
+        }
+        let mut table = Table { data: Vec::new(), widths, dialect, stream: Some(stream), ..Table::new() };
+        table.infer_types();
+        Ok(table)
+    }
+
+    /// Whether rows are read from the file as needed, which allows only changing values.
+    pub fn is_streamed(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// How much of a streamed file has been read to find its rows,
+    /// as a percent, while it is still being read.
+    pub fn loading(&self) -> Option<u64> {
+        self.stream.as_ref().and_then(|s| s.loading())
+    }
+
+    /// What went wrong reading a streamed file, if anything.
+    pub fn load_error(&self) -> Option<String> {
+        self.stream.as_ref().and_then(|s| s.error())
+    }
+
+    /// Reads the file again in the table's dialect, dropping all changes
+    /// and filters but keeping how the table is shown.
+    pub fn reload(&mut self, path: &str) -> Result<()> {
+        let table = if self.is_streamed() {
+            Table::stream_path(path, self.dialect)?
+        } else {
+            Table::from_path(path, self.dialect)?
+        };
+        self.data = table.data;
+        self.widths = table.widths;
+        self.original = table.original;
+        self.origin = table.origin;
+        self.stream = table.stream;
+        self.history = History::new();
+        self.modified = false;
+        self.visible = None;
+        self.infer_types();
+        Ok(())
+    }
+
+    pub fn has_header(&self) -> bool {
+        self.header
+    }
+
+    /// Sets whether the first row names the columns, or is data like the rest.
+    pub fn set_header(&mut self, header: bool) {
+        if header != self.header {
+            self.header = header;
+            self.infer_types();
+        }
+    }
+
+    /// Guesses the type of every column from its values below the
+    /// header, or from the first rows of a streamed file.
+    fn infer_types(&mut self) {
+        let (w, _) = self.dims();
+        let streamed: Vec<Vec<String>> = match &self.stream {
+            Some(stream) => (0..stream.len().min(INFER_ROWS)).map(|r| stream.row(r)).collect(),
+            None => Vec::new(),
+        };
+        let rows = if self.stream.is_some() { &streamed } else { &self.data };
+        let body = &rows[self.body_start().min(rows.len())..];
+        self.types = (0..w)
+            .map(|x| Type::infer(body.iter().filter_map(|row| row.get(x)).map(|v| v.as_str())))
             .collect();
+    }
+
+    /// What kind of values the column holds.
+    pub fn col_type(&self, x: usize) -> &Type {
+        &self.types[x]
+    }
+
+    /// Names of the columns followed by their types, as shown.
+    fn labels(&self) -> Vec<String> {
+        self.col_names()
+            .into_iter()
+            .zip(self.types.iter())
+            .map(|(name, t)| match t.tag() {
+                Some(tag) => format!("{}:{}", name, tag),
+                None => name,
+            })
+            .collect()
+    }
 
-        data.push(headers);
+    /// The first row below the header, which is the first row without one.
+    pub fn body_start(&self) -> usize {
+        self.header as usize
+    }
 
-        for record in rdr.into_records() {
-            let strs = record
-                .unwrap()
-                .into_iter()
-                .map(|x| x.to_owned())
-                .collect();
-            data.push(strs);
+    /// Names of the columns: the header, or column numbers without one.
+    pub fn col_names(&self) -> Vec<String> {
+        let (w, _) = self.dims();
+        if self.header {
+            self.row(0)
+        } else {
+            (0..w).map(|x| x.to_string()).collect()
         }
-        
-        Table { data }
+    }
+
+    /// Limits how wide every column is drawn, or lets them be as wide as their values.
+    pub fn set_max_width(&mut self, max: Option<usize>) {
+        self.max_width = max;
+    }
+
+    /// Limits how wide a column is drawn, over the limit for every column.
+    pub fn set_col_max(&mut self, x: usize, max: Option<usize>) {
+        self.col_max.insert(x, max);
+    }
+
+    /// Forgets the limit on a column, so it has the limit for every column.
+    pub fn reset_col_max(&mut self, x: usize) {
+        self.col_max.remove(&x);
+    }
+
+    /// How wide a column may be drawn.
+    fn col_limit(&self, x: usize) -> Option<usize> {
+        self.col_max.get(&x).copied().unwrap_or(self.max_width)
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// Changes how the table will be written when next saved.
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
     }
 
     /// Returns the dimensions of the data within the table.
-    /// Cache this value
     pub fn dims(&self) -> (usize, usize) {
-        let dimy = self.data.len();
+        if let Some(stream) = &self.stream {
+            return (stream.width(), stream.len());
+        }
+        let dimy = match &self.visible {
+            Some(rows) => rows.len(),
+            None => self.data.len(),
+        };
         let dimx = self.data[0].len();
         (dimx, dimy)
     }
 
-    /// Calculates the max characters needed to display all values in a column.
-    /// Needs rewrite to be more efficient (after removing headers)
-    /// Also cache this value
+    /// Returns the index into the data of a row in view.
+    fn data_row(&self, y: usize) -> usize {
+        match &self.visible {
+            Some(rows) => rows[y],
+            None => y,
+        }
+    }
+
+    /// Returns where in the data to insert a row so it is in view at `y`.
+    fn data_insert_row(&self, y: usize) -> usize {
+        let (_, h) = self.dims();
+        if y < h {
+            self.data_row(y)
+        } else {
+            self.data_row(h - 1) + 1
+        }
+    }
+
+    /// Hides the rows in view, other than the header, where `f` is false.
+    /// Returns the number of rows left in view below the header, or `None`
+    /// without changing the view if that would leave no rows at all.
+    pub fn filter_rows<F: Fn(&[String]) -> bool>(&mut self, f: F) -> Option<usize> {
+        let (_, h) = self.dims();
+        let start = self.body_start();
+        let rows: Vec<usize> = (0..h)
+            .map(|y| self.data_row(y))
+            .filter(|r| *r < start || f(&self.data[*r]))
+            .collect();
+        if rows.is_empty() {
+            return None;
+        }
+        let n = rows.len() - start;
+        self.visible = Some(rows);
+        Some(n)
+    }
+
+    /// Shows every row again.
+    pub fn unfilter(&mut self) {
+        self.visible = None;
+    }
+
+    /// If filtered, the number of rows in view and the number
+    /// of rows in all, neither counting the header.
+    pub fn filtered(&self) -> Option<(usize, usize)> {
+        let start = self.body_start();
+        self.visible.as_ref().map(|rows| (rows.len() - start, self.data.len() - start))
+    }
+
+    /// Calculates the terminal columns needed to display every value in
+    /// each column, and its label, up to its limit.  Rows out of view
+    /// count too, so filtering doesn't shift the columns.
     pub fn col_widths(&self) -> Vec<i32> {
-        self.data.iter().fold(
-            self.data[0].iter().map(|h| h.len()).map(|x| x as i32).collect::<Vec<i32>>(),
-            |maxes, cur| {
-                maxes
-                    .iter()
-                    .zip(cur.iter())
-                    .map(|(m, c)| std::cmp::max::<i32>(*m, c.len() as i32))
-                    .collect::<Vec<i32>>()
-            },
-        )
-    }
-
-    /// Add iterator for column widths along with row value?
-    /// for (cell, width) in row.iter().zip(table.col_widths().iter()) {
-    /// actually do fmt
-    pub fn fmt_iter(&self) -> Vec<Vec<String>>{
-        let widths = self.col_widths();
-        self.data.iter().map(|row|
-            row.iter().zip(widths.iter()).map(|(cell, width)| {
-                let mut p = cell.clone();
-                for _ in cell.len()..(*width as usize) {
-                    p.push(' ');
+        let streamed = self.stream.as_ref().map(|s| s.widest());
+        let labels = self.labels();
+        self.widths
+            .iter()
+            .enumerate()
+            .map(|(x, counts)| {
+                let mut w = counts.keys().next_back().copied().unwrap_or(0);
+                if let Some(widest) = &streamed {
+                    // rows read since opening may be wider
+                    w = w.max(widest[x]);
                 }
-                p
-            }).collect()
-        ).collect()
+                // the header with its type, or the column numbers shown in its place
+                w = w.max(text::width(&labels[x]));
+                match self.col_limit(x) {
+                    Some(max) => w.min(max) as i32,
+                    None => w as i32,
+                }
+            })
+            .collect()
     }
 
-    /// Gets the internal data
+    /// Reads rows in view from a streamed file ahead of drawing them,
+    /// so their columns are wide enough.
+    pub fn load_rows(&self, rows: Range<usize>) {
+        if let Some(stream) = &self.stream {
+            for y in rows {
+                stream.row(self.data_row(y));
+            }
+        }
+    }
+
+    /// Fits each cell of a row in view to the width of its column,
+    /// with numbers to the right.  The header is shown with the types.
+    pub fn fmt_row(&self, y: usize, widths: &[i32]) -> Vec<String> {
+        if self.header && y == 0 {
+            return self.fmt_names(widths);
+        }
+        self.row(y)
+            .iter()
+            .zip(widths.iter())
+            .zip(self.types.iter())
+            .map(|((cell, width), t)| {
+                if t.is_numeric() {
+                    text::fit_right(cell, *width as usize)
+                } else {
+                    text::fit(cell, *width as usize)
+                }
+            })
+            .collect()
+    }
+
+    /// The column names and their types fit to the widths of the columns.
+    pub fn fmt_names(&self, widths: &[i32]) -> Vec<String> {
+        pad(&self.labels(), widths)
+    }
+
+    /// Gets the internal data, including rows filtered out of view
     /// Should be formalized as iterators
     pub fn rows(&self) -> Vec<Vec<String>> {
         self.data.clone()
@@ -92,46 +455,109 @@ impl Table {
 
     /// Gets a value in the table.
     pub fn get(&self, c: Cursor) -> String {
-        self.data[c.y][c.x].clone()
+        self.value(self.data_row(c.y), c.x)
+    }
+
+    /// Gets a value by its index into the data, reading it from the file if streamed.
+    fn value(&self, r: usize, x: usize) -> String {
+        match &self.stream {
+            Some(stream) => stream.row(r)[x].clone(),
+            None => self.data[r][x].clone(),
+        }
+    }
+
+    /// Finds the next cell from the cursor whose value satisfies `f`, reading
+    /// left to right and top to bottom, or the reverse if `backward`.
+    /// The search wraps around the table, ending back at the cursor.
+    /// Returns the cell found and whether the search wrapped around.
+    pub fn find<F: Fn(&str) -> bool>(&self, from: Cursor, backward: bool, f: F) -> Option<(Cursor, bool)> {
+        let (w, h) = self.dims();
+        let n = w * h;
+        let start = from.y * w + from.x;
+        for step in 1..=n {
+            let i = if backward {
+                (start + n - step) % n
+            } else {
+                (start + step) % n
+            };
+            let c = Cursor { x: i % w, y: i / w };
+            if f(&self.value(self.data_row(c.y), c.x)) {
+                let wrapped = if backward { i >= start } else { i <= start };
+                return Some((c, wrapped));
+            }
+        }
+        None
     }
 
     /// Updates a value in the table.
     pub fn update(&mut self, c: Cursor, new: String) {
-        self.data[c.y][c.x] = new;
+        let y = self.data_row(c.y);
+        let old = self.value(y, c.x);
+        if old == new {
+            return
+        }
+        self.apply(Edit::Cell { x: c.x, y, old, new }, c);
+    }
+
+    /// Changes every cell in the rectangle between the corners, inclusive,
+    /// to what `f` returns for its value, as one undo step.
+    pub fn update_range<F: Fn(&str) -> String>(&mut self, tl: Cursor, br: Cursor, c: Cursor, f: F) {
+        self.begin_group(c);
+        for y in tl.y..=br.y {
+            for x in tl.x..=br.x {
+                let at = Cursor { x, y };
+                let new = f(&self.get(at));
+                self.update(at, new);
+            }
+        }
+        self.end_group();
+    }
+
+    /// Overwrites cells from the cursor with rows of values,
+    /// as far as the table goes, as one undo step.
+    pub fn overwrite(&mut self, c: Cursor, rows: &[Vec<String>]) {
+        let (w, h) = self.dims();
+        self.begin_group(c);
+        for (y, row) in (c.y..h).zip(rows) {
+            for (x, value) in (c.x..w).zip(row) {
+                self.update(Cursor { x, y }, value.clone());
+            }
+        }
+        self.end_group();
     }
 
     /// Adds row before the cursor location.
     pub fn add_row_before(&mut self, c: Cursor) {
-        self.add_row(c.y);
+        self.add_row(c.y, c);
     }
 
     /// Adds row after the cursor location.
     pub fn add_row_after(&mut self, c: Cursor) {
-        self.add_row(c.y + 1);
+        self.add_row(c.y + 1, c);
     }
 
     /// Adds new row before the given index.
-    fn add_row(&mut self, r: usize) {
+    fn add_row(&mut self, r: usize, c: Cursor) {
         let (w, _) = self.dims();
-        let new_row = vec![String::new(); w];
-        self.data.insert(r, new_row);
+        let row = vec![String::new(); w];
+        let y = self.data_insert_row(r);
+        self.apply(Edit::InsertRow { y, row }, c);
     }
 
     /// Adds column before the cursor location.
     pub fn add_col_before(&mut self, c: Cursor) {
-        self.add_col(c.x);
+        self.add_col(c.x, c);
     }
 
     /// Adds column after the cursor location.
     pub fn add_col_after(&mut self, c: Cursor) {
-        self.add_col(c.x + 1);
+        self.add_col(c.x + 1, c);
     }
 
     /// Inserts new column at index
-    fn add_col(&mut self, c: usize) {
-        for row in &mut self.data {
-            row.insert(c, String::new());
-        }
+    fn add_col(&mut self, x: usize, c: Cursor) {
+        let col = vec![String::new(); self.data.len()];
+        self.apply(Edit::InsertCol { x, col }, c);
     }
 
     /// Clears the value in the table.
@@ -139,24 +565,426 @@ impl Table {
         self.update(c, String::new());
     }
 
-    /// Deletes the row the cursor lies.
-    pub fn delete_row(&mut self, c: &mut Cursor) {
-        self.data.remove(c.y);
+    /// Inserts rows before the given index, padding or cutting
+    /// them to the width of the table.
+    pub fn insert_rows(&mut self, y: usize, rows: &[Vec<String>], c: Cursor) {
+        let (w, _) = self.dims();
+        self.begin_group(c);
+        for (i, row) in rows.iter().enumerate() {
+            let mut row = row.clone();
+            row.resize(w, String::new());
+            let at = self.data_insert_row(y + i);
+            self.apply(Edit::InsertRow { y: at, row }, c);
+        }
+        self.end_group();
+    }
+
+    /// Inserts columns before the given index, padding or cutting
+    /// them to the height of the table, including rows out of view.
+    pub fn insert_cols(&mut self, x: usize, cols: &[Vec<String>], c: Cursor) {
+        let h = self.data.len();
+        self.begin_group(c);
+        for (i, col) in cols.iter().enumerate() {
+            let mut col = col.clone();
+            col.resize(h, String::new());
+            self.apply(Edit::InsertCol { x: x + i, col }, c);
+        }
+        self.end_group();
+    }
+
+    /// Reorders the rows so row `i` becomes what was row `order[i]`.
+    /// These are indices of every row, in view or not, as in `rows`.
+    pub fn permute_rows(&mut self, order: Vec<usize>, c: Cursor) {
+        self.apply(Edit::Permute { order }, c);
+    }
+
+    /// Finds a column by its header, ignoring case, or by its number.
+    pub fn find_col(&self, name: &str) -> Option<usize> {
+        let (w, _) = self.dims();
+        self.col_names()
+            .iter()
+            .position(|h| h.to_lowercase() == name.to_lowercase())
+            .or_else(|| name.parse().ok().filter(|x| *x < w))
+    }
+
+    /// Gets a copy of a row.
+    pub fn row(&self, y: usize) -> Vec<String> {
+        match &self.stream {
+            Some(stream) => {
+                let mut row = stream.row(self.data_row(y));
+                row.truncate(stream.width());
+                row
+            }
+            None => self.data[self.data_row(y)].clone(),
+        }
+    }
+
+    /// Gets a copy of a column, including rows out of view.
+    pub fn col(&self, x: usize) -> Vec<String> {
+        self.data.iter().map(|row| row[x].clone()).collect()
+    }
+
+    /// Deletes `n` rows from the one the cursor lies, or as many as
+    /// there are below it, returning what was in them in order.
+    /// The last remaining row is cleared instead.
+    pub fn delete_rows(&mut self, c: &mut Cursor, n: usize) -> Vec<Vec<String>> {
+        let (w, h) = self.dims();
+        let mut rows = Vec::new();
+        self.begin_group(*c);
+        for _ in 0..n.min(h - c.y) {
+            // the rows below move up into the cursor's row
+            let y = self.data_row(c.y);
+            let row = self.row(c.y);
+            self.apply(Edit::DeleteRow { y, row: row.clone() }, *c);
+            rows.push(row);
+            if self.data.is_empty() {
+                self.apply(Edit::InsertRow { y: 0, row: vec![String::new(); w] }, *c);
+                break
+            }
+        }
+        self.end_group();
+        c.clamp(self);
+        rows
+    }
+
+    /// Deletes the column the cursor lies, returning what was in it.
+    /// The last remaining column is cleared instead.
+    pub fn delete_col(&mut self, c: &mut Cursor) -> Vec<String> {
+        let (w, _) = self.dims();
+        let col = self.col(c.x);
+        self.begin_group(*c);
+        self.apply(Edit::DeleteCol { x: c.x, col: col.clone() }, *c);
+        if w == 1 {
+            self.apply(Edit::InsertCol { x: 0, col: vec![String::new(); self.data.len()] }, *c);
+        }
+        self.end_group();
+        c.clamp(self);
+        col
+    }
+
+    /// Performs an edit and records it so it can be undone.
+    fn apply(&mut self, edit: Edit, c: Cursor) {
+        self.perform(&edit);
+        self.history.record(edit, c);
+        self.modified = true;
+    }
+
+    /// Changes the data, keeping the same rows in view
+    /// and column widths with their columns.
+    /// Inserted rows are always in view.
+    fn perform(&mut self, edit: &Edit) {
+        if let Some(stream) = &mut self.stream {
+            // only values can be changed while streaming
+            if let Edit::Cell { x, y, new, .. } = edit {
+                stream.update(*y, *x, new.clone());
+                if *y >= self.header as usize {
+                    self.types[*x].learn(new);
+                }
+            }
+            self.count_edit(edit);
+            return;
+        }
+        edit.apply(&mut self.data);
+        self.count_edit(edit);
+        match edit {
+            Edit::Cell { x, y, new, .. } if *y >= self.body_start() => self.types[*x].learn(new),
+            Edit::InsertCol { x, col } => {
+                let body = &col[self.body_start().min(col.len())..];
+                self.types.insert(*x, Type::infer(body.iter().map(|v| v.as_str())));
+                self.col_max = self.col_max.drain()
+                    .map(|(c, max)| (if c >= *x { c + 1 } else { c }, max))
+                    .collect();
+            }
+            Edit::InsertRow { y, .. } => self.origin.insert(*y, None),
+            Edit::DeleteRow { y, .. } => {
+                self.origin.remove(*y);
+            }
+            Edit::Permute { order } => self.origin = order.iter().map(|o| self.origin[*o]).collect(),
+            Edit::DeleteCol { x, .. } => {
+                self.types.remove(*x);
+                self.col_max = self.col_max.drain()
+                    .filter(|(c, _)| c != x)
+                    .map(|(c, max)| (if c > *x { c - 1 } else { c }, max))
+                    .collect();
+            }
+            _ => {}
+        }
+        let rows = match &mut self.visible {
+            Some(rows) => rows,
+            None => return,
+        };
+        match edit {
+            Edit::InsertRow { y, .. } => {
+                for r in rows.iter_mut().filter(|r| **r >= *y) {
+                    *r += 1;
+                }
+                let at = rows.partition_point(|r| r < y);
+                rows.insert(at, *y);
+            }
+            Edit::DeleteRow { y, .. } => {
+                rows.retain(|r| r != y);
+                for r in rows.iter_mut().filter(|r| **r > *y) {
+                    *r -= 1;
+                }
+            }
+            Edit::Permute { order } => {
+                let mut moved_to = vec![0; order.len()];
+                for (i, o) in order.iter().enumerate() {
+                    moved_to[*o] = i;
+                }
+                for r in rows.iter_mut() {
+                    *r = moved_to[*r];
+                }
+                rows.sort_unstable();
+            }
+            _ => {}
+        }
+        if rows.is_empty() {
+            self.visible = None;
+        }
+    }
+
+    /// Keeps the counts of cell widths up to date with an edit.
+    fn count_edit(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Cell { x, old, new, .. } => {
+                uncount(&mut self.widths[*x], old);
+                count(&mut self.widths[*x], new);
+            }
+            Edit::InsertRow { row, .. } => {
+                for (counts, cell) in self.widths.iter_mut().zip(row) {
+                    count(counts, cell);
+                }
+            }
+            Edit::DeleteRow { row, .. } => {
+                for (counts, cell) in self.widths.iter_mut().zip(row) {
+                    uncount(counts, cell);
+                }
+            }
+            Edit::InsertCol { x, col } => {
+                let mut counts = BTreeMap::new();
+                for cell in col {
+                    count(&mut counts, cell);
+                }
+                self.widths.insert(*x, counts);
+            }
+            Edit::DeleteCol { x, .. } => {
+                self.widths.remove(*x);
+            }
+            Edit::Permute { .. } => {}
+        }
+    }
+
+    /// Whether the table has changed since it was loaded or last saved.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Groups all following edits into one undo step, until `end_group`.
+    pub fn begin_group(&mut self, c: Cursor) {
+        self.history.begin_group(c);
+    }
+
+    /// Ends the current undo group.
+    pub fn end_group(&mut self) {
+        self.history.end_group();
     }
 
-    /// Deletes the column the cursor lies.
-    pub fn delete_col(&mut self, c: &mut Cursor) {
-        for row in &mut self.data {
-            row.remove(c.x);
+    /// Reverts the last step of edits.
+    /// Returns where the cursor was before the step, if there was one.
+    pub fn undo(&mut self) -> Option<Cursor> {
+        let step = self.history.pop_undo()?;
+        for edit in step.edits.iter().rev() {
+            self.perform(&edit.inverse());
         }
+        let c = step.cursor;
+        self.history.push_redo(step);
+        self.modified = true;
+        Some(c)
     }
 
-    /// Writes the data as a csv to the given path.
-    pub fn save_to_path(&self, path: String) {
-        let mut wtr = csv::Writer::from_path(path).unwrap();
-        for row in self.data.clone().into_iter() {
-            wtr.write_record(row).unwrap();
+    /// Reapplies the last undone step of edits.
+    /// Returns where the cursor was before the step, if there was one.
+    pub fn redo(&mut self) -> Option<Cursor> {
+        let step = self.history.pop_redo()?;
+        for edit in step.edits.iter() {
+            self.perform(edit);
         }
-        wtr.flush().unwrap();
+        let c = step.cursor;
+        self.history.push_undo(step);
+        self.modified = true;
+        Some(c)
+    }
+
+    /// Writes the data to the given path and marks the table as saved.
+    pub fn save_to_path(&mut self, path: &str) -> Result<()> {
+        self.write_to_path(path)?;
+        self.modified = false;
+        Ok(())
+    }
+
+    /// Writes the data as a csv to the given path, in the table's dialect.
+    /// Rows that haven't changed since they were read are written
+    /// byte for byte as they were, unless the dialect has changed.
+    pub fn write_to_path(&mut self, path: &str) -> Result<()> {
+        if let Some(stream) = &self.stream {
+            stream.save(path, self.dialect)?;
+            if same_file(path, stream.path()) {
+                // the rows have moved in the file, so find them again
+                self.stream = Some(Stream::open(path, self.dialect)?.0);
+            }
+            return Ok(());
+        }
+        self.write_to(File::create(path)?)
+    }
+
+    /// Writes the data to the writer and marks the table as saved.
+    pub fn save_to<W: Write>(&mut self, wtr: W) -> Result<()> {
+        self.write_to(wtr)?;
+        self.modified = false;
+        Ok(())
+    }
+
+    /// Writes the data as a csv to the writer, as `write_to_path` does.
+    pub fn write_to<W: Write>(&self, wtr: W) -> Result<()> {
+        if let Some(stream) = &self.stream {
+            return stream.write(wtr, self.dialect);
+        }
+        let original = self.original.as_ref().filter(|o| o.dialect == self.dialect);
+        let terminator = self.dialect.terminator_bytes();
+
+        let mut wtr = BufWriter::new(wtr);
+        if self.dialect.bom {
+            wtr.write_all(BOM)?;
+        }
+        // rows are read again from the original to check they haven't changed
+        let mut rdr = original.map(|o| {
+            o.dialect.reader().has_headers(false).flexible(true).from_reader(io::Cursor::new(&o.bytes[..]))
+        });
+        for (i, row) in self.data.iter().enumerate() {
+            let line = match (original, &mut rdr, self.origin[i]) {
+                (Some(o), Some(rdr), Some(k)) => o.line(rdr, k, row),
+                _ => None,
+            };
+            match line {
+                Some(line) => wtr.write_all(line)?,
+                None => wtr.write_all(&self.dialect.write_row(row)?)?,
+            }
+            let last = i + 1 == self.data.len();
+            if !last || original.is_none_or(|o| o.final_terminator) {
+                wtr.write_all(&terminator)?;
+            }
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// Counts how many cells of each width are in each column.
+fn count_widths(data: &[Vec<String>]) -> Vec<BTreeMap<usize, usize>> {
+    let mut widths = vec![BTreeMap::new(); data.first().map_or(0, |row| row.len())];
+    for row in data {
+        for (counts, cell) in widths.iter_mut().zip(row) {
+            count(counts, cell);
+        }
+    }
+    widths
+}
+
+fn count(counts: &mut BTreeMap<usize, usize>, cell: &str) {
+    *counts.entry(text::width(cell)).or_insert(0) += 1;
+}
+
+fn uncount(counts: &mut BTreeMap<usize, usize>, cell: &str) {
+    let w = text::width(cell);
+    if let Some(n) = counts.get_mut(&w) {
+        *n -= 1;
+        if *n == 0 {
+            counts.remove(&w);
+        }
+    }
+}
+
+/// Fits each cell to the width of its column, padding it with
+/// spaces or cutting it short with an ellipsis.
+fn pad(row: &[String], widths: &[i32]) -> Vec<String> {
+    row.iter()
+        .zip(widths.iter())
+        .map(|(cell, width)| text::fit(cell, *width as usize))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(bytes: &[u8]) -> Table {
+        Table::from_bytes(bytes, Dialect::detect_bytes(bytes)).unwrap()
+    }
+
+    fn written(table: &Table) -> Vec<u8> {
+        let mut out = Vec::new();
+        table.write_to(&mut out).unwrap();
+        out
+    }
+
+    fn assert_round_trip(bytes: &[u8]) {
+        assert_eq!(written(&read(bytes)), bytes);
+    }
+
+    #[test]
+    fn duplicate_rows_keep_their_quoting() {
+        assert_round_trip(b"a,b\n\"x\",y\nx,y\n");
+        assert_round_trip(b"a,b\nx,y\n\"x\",\"y\"\nx,y\n");
+    }
+
+    #[test]
+    fn crlf() {
+        assert_round_trip(b"a,b\r\n1,2\r\n\"3\",4\r\n");
+    }
+
+    #[test]
+    fn bom() {
+        assert_round_trip(b"\xEF\xBB\xBFa,b\n1,2\n");
+    }
+
+    #[test]
+    fn no_final_terminator() {
+        assert_round_trip(b"a,b\n1,2");
+    }
+
+    #[test]
+    fn ragged_rows() {
+        assert_round_trip(b"a,b,c\n1\n1,2\n1,2,3\n");
+    }
+
+    #[test]
+    fn appended_column_is_written() {
+        let mut t = read(b"a,b\n1,2\n3,4\n");
+        t.add_col_after(Cursor { x: 1, y: 0 });
+        t.update(Cursor { x: 2, y: 0 }, "c".to_string());
+        assert_eq!(written(&t), b"a,b,c\n1,2,\n3,4,\n");
+        t.undo();
+        t.undo();
+        assert_eq!(written(&t), b"a,b\n1,2\n3,4\n");
+    }
+
+    #[test]
+    fn edited_cell_is_written_anew() {
+        let mut t = read(b"a,b\n\"x\",y\n\"x\",y\n");
+        t.update(Cursor { x: 0, y: 2 }, "z".to_string());
+        assert_eq!(written(&t), b"a,b\n\"x\",y\nz,y\n");
+        t.undo();
+        assert_eq!(written(&t), b"a,b\n\"x\",y\n\"x\",y\n");
+    }
+
+    #[test]
+    fn rows_keep_their_bytes_when_moved() {
+        let mut t = read(b"a,b\n\"x\",1\ny,\"2\"\n");
+        t.permute_rows(vec![0, 2, 1], Cursor::new());
+        assert_eq!(written(&t), b"a,b\ny,\"2\"\n\"x\",1\n");
+        let mut c = Cursor { x: 0, y: 1 };
+        t.delete_rows(&mut c, 1);
+        assert_eq!(written(&t), b"a,b\n\"x\",1\n");
     }
 }
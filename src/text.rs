@@ -0,0 +1,92 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Columns of the terminal a grapheme takes up.
+/// Joined emoji and flags still only take up two.
+fn grapheme_width(g: &str) -> usize {
+    shown(g).width().min(2)
+}
+
+/// What is drawn for a grapheme, which is a space for
+/// line breaks, tabs and other control characters.
+fn shown(g: &str) -> &str {
+    if g.chars().any(char::is_control) { " " } else { g }
+}
+
+/// The text as drawn on one line.
+fn clean(s: &str) -> String {
+    s.graphemes(true).map(shown).collect()
+}
+
+/// Columns of the terminal the text takes up.
+pub fn width(s: &str) -> usize {
+    s.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Cuts the text to at most `max` columns without splitting a grapheme.
+/// A wide grapheme that would be split is replaced by spaces.
+pub fn truncate(s: &str, max: usize) -> String {
+    let mut out = String::new();
+    let mut used = 0;
+    for g in s.graphemes(true) {
+        let w = grapheme_width(g);
+        if used + w > max {
+            out.push_str(&" ".repeat(max - used));
+            break
+        }
+        out.push_str(shown(g));
+        used += w;
+    }
+    out
+}
+
+/// Pads the text with spaces to at least `w` columns.
+pub fn pad(s: &str, w: usize) -> String {
+    let mut p = clean(s);
+    for _ in width(s)..w {
+        p.push(' ');
+    }
+    p
+}
+
+/// Pads the text with spaces to `w` columns, or cuts it
+/// to end with an ellipsis if it is wider.
+pub fn fit(s: &str, w: usize) -> String {
+    if width(s) <= w {
+        return pad(s, w);
+    }
+    match w {
+        0 => String::new(),
+        _ => format!("{}…", truncate(s, w - 1)),
+    }
+}
+
+/// Like `fit`, but pads with spaces on the left, for numbers.
+pub fn fit_right(s: &str, w: usize) -> String {
+    let sw = width(s);
+    if sw > w {
+        return fit(s, w);
+    }
+    format!("{}{}", " ".repeat(w - sw), clean(s))
+}
+
+/// Breaks the text into lines at most `w` columns wide,
+/// keeping the line breaks in the text.
+pub fn wrap(s: &str, w: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in s.lines() {
+        let mut cur = String::new();
+        let mut used = 0;
+        for g in line.graphemes(true) {
+            let gw = grapheme_width(g);
+            if used + gw > w && !cur.is_empty() {
+                lines.push(std::mem::take(&mut cur));
+                used = 0;
+            }
+            cur.push_str(shown(g));
+            used += gw;
+        }
+        lines.push(cur);
+    }
+    lines
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+
+/// Most values a column can have to be an enum.
+const ENUM_MAX: usize = 8;
+
+/// The kind of values a column holds, guessed from the values it has.
+/// Empty cells fit every type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Text,
+    Integer,
+    Float,
+    /// `true` or `false`, or `yes` or `no`, in any case
+    Bool,
+    /// Like `2021-03-14`
+    Date,
+    /// A date with a time, like `2021-03-14 15:09` or `2021-03-14T15:09:26Z`
+    DateTime,
+    /// One of a few values, repeated often, in sorted order
+    Enum(Vec<String>),
+}
+
+impl Type {
+    /// Guesses the type of a column from its values, choosing the
+    /// narrowest type every value fits.  A column with only a few
+    /// values, each appearing at least twice on average, is an enum.
+    pub fn infer<'a, I: IntoIterator<Item = &'a str>>(values: I) -> Self {
+        let (mut int, mut float, mut boolean, mut date, mut datetime) = (true, true, true, true, true);
+        let mut distinct: Vec<&str> = Vec::new();
+        let mut many = false;
+        let mut n = 0;
+        for v in values.into_iter().map(str::trim).filter(|v| !v.is_empty()) {
+            n += 1;
+            int = int && is_integer(v);
+            float = float && is_float(v);
+            boolean = boolean && is_bool(v);
+            date = date && is_date(v);
+            datetime = datetime && is_datetime(v);
+            if !many && !distinct.contains(&v) {
+                many = distinct.len() == ENUM_MAX;
+                distinct.push(v);
+            }
+        }
+
+        if n == 0 {
+            Type::Text
+        } else if boolean {
+            Type::Bool
+        } else if int {
+            Type::Integer
+        } else if float {
+            Type::Float
+        } else if date {
+            Type::Date
+        } else if datetime {
+            Type::DateTime
+        } else if !many && distinct.len() * 2 <= n {
+            let mut values: Vec<String> = distinct.iter().map(|v| v.to_string()).collect();
+            values.sort();
+            Type::Enum(values)
+        } else {
+            Type::Text
+        }
+    }
+
+    /// Whether the value is one of this type.  Only values
+    /// already in an enum are of its type.
+    pub fn parses(&self, value: &str) -> bool {
+        let v = value.trim();
+        v.is_empty() || match self {
+            Type::Text => true,
+            Type::Integer => is_integer(v),
+            Type::Float => is_float(v),
+            Type::Bool => is_bool(v),
+            Type::Date => is_date(v),
+            Type::DateTime => is_datetime(v),
+            Type::Enum(values) => values.iter().any(|e| e == v),
+        }
+    }
+
+    /// Adds a value to an enum, keeping the values sorted.
+    pub fn learn(&mut self, value: &str) {
+        let v = value.trim();
+        if let Type::Enum(values) = self {
+            if let Err(at) = values.binary_search_by(|e| e.as_str().cmp(v)) {
+                if !v.is_empty() {
+                    values.insert(at, v.to_string());
+                }
+            }
+        }
+    }
+
+    /// Whether values are numbers, which are drawn aligned to the right.
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Integer | Type::Float)
+    }
+
+    /// A short name shown after the column name, or `None` for text.
+    pub fn tag(&self) -> Option<&'static str> {
+        match self {
+            Type::Text => None,
+            Type::Integer => Some("int"),
+            Type::Float => Some("float"),
+            Type::Bool => Some("bool"),
+            Type::Date => Some("date"),
+            Type::DateTime => Some("datetime"),
+            Type::Enum(_) => Some("enum"),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Type::Text => "text",
+            Type::Integer => "integer",
+            Type::Float => "number",
+            Type::Bool => "true or false",
+            Type::Date => "date",
+            Type::DateTime => "date and time",
+            Type::Enum(_) => "known value",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn is_integer(v: &str) -> bool {
+    v.parse::<i64>().is_ok()
+}
+
+/// Numbers written with digits, so not `inf` or `NaN`.
+fn is_float(v: &str) -> bool {
+    v.parse::<f64>().is_ok() && v.bytes().any(|b| b.is_ascii_digit())
+}
+
+fn is_bool(v: &str) -> bool {
+    matches!(v.to_lowercase().as_str(), "true" | "false" | "yes" | "no")
+}
+
+/// A date as `YYYY-MM-DD` that exists on the calendar.
+fn is_date(v: &str) -> bool {
+    let b = v.as_bytes();
+    if b.len() != 10 || b[4] != b'-' || b[7] != b'-' {
+        return false;
+    }
+    match (number(&v[0..4]), number(&v[5..7]), number(&v[8..10])) {
+        (Some(y), Some(m), Some(d)) => (1..=12).contains(&m) && d >= 1 && d <= days_in_month(y, m),
+        _ => false,
+    }
+}
+
+/// A date, then `T` or a space and a time as `HH:MM`, with optional
+/// seconds and fraction, then optionally `Z` or an offset like `+01:00`.
+fn is_datetime(v: &str) -> bool {
+    if v.len() < 11 || !v.is_char_boundary(10) || !is_date(&v[..10]) {
+        return false;
+    }
+    let rest = &v[10..];
+    let time = match rest.strip_prefix('T').or_else(|| rest.strip_prefix(' ')) {
+        Some(time) => time,
+        None => return false,
+    };
+    let time = time.strip_suffix('Z').unwrap_or(time);
+    let time = match time.rfind(['+', '-']) {
+        Some(at) if is_clock(&time[at + 1..], false) => &time[..at],
+        _ => time,
+    };
+    is_clock(time, true)
+}
+
+/// A time as `HH:MM`, with `:SS` and a fraction if `seconds` are allowed.
+fn is_clock(v: &str, seconds: bool) -> bool {
+    let mut parts = v.splitn(3, ':');
+    let h = parts.next().filter(|h| h.len() == 2).and_then(number);
+    let m = parts.next().filter(|m| m.len() == 2).and_then(number);
+    let s = match parts.next() {
+        None => true,
+        Some(_) if !seconds => false,
+        Some(s) => {
+            let whole = s.split_once('.').map_or(s, |(whole, frac)| {
+                if !frac.is_empty() && frac.bytes().all(|b| b.is_ascii_digit()) { whole } else { "" }
+            });
+            whole.len() == 2 && number(whole).is_some_and(|s| s < 61)
+        }
+    };
+    h.is_some_and(|h| h < 24) && m.is_some_and(|m| m < 60) && s
+}
+
+fn number(v: &str) -> Option<u32> {
+    if v.bytes().all(|b| b.is_ascii_digit()) {
+        v.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn days_in_month(y: u32, m: u32) -> u32 {
+    match m {
+        2 if y.is_multiple_of(4) && (!y.is_multiple_of(100) || y.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}